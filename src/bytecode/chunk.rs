@@ -0,0 +1,115 @@
+use crate::parsing::expressions::Value;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    /// u16 constant index
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// u16 constant index of the name
+    DefineGlobal,
+    /// u16 constant index of the name
+    GetGlobal,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    /// u8 argument count
+    Call,
+    Return,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 22] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::DefineGlobal,
+        OpCode::GetGlobal,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Call,
+        OpCode::Return,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OpCode::ALL.get(byte as usize).copied()
+    }
+}
+
+/// a compiled sequence of instructions with the constants and source lines they refer to
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// run-length encoded (line, instruction bytes on that line)
+    pub lines: Vec<(u32, usize)>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: u32) {
+        self.code.push(byte);
+        match self.lines.last_mut() {
+            Some((last, count)) if *last == line => *count += 1,
+            _ => self.lines.push((line, 1)),
+        }
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: u32) {
+        self.write(op as u8, line);
+    }
+
+    /// None once the chunk holds as many constants as a u16 operand can address
+    pub fn add_constant(&mut self, value: Value) -> Option<u16> {
+        if self.constants.len() > u16::MAX as usize {
+            return None;
+        }
+        self.constants.push(value);
+        Some((self.constants.len() - 1) as u16)
+    }
+
+    /// source line of the instruction byte at `offset`
+    pub fn line(&self, offset: usize) -> u32 {
+        let mut end = 0;
+        for (line, count) in self.lines.iter() {
+            end += count;
+            if offset < end {
+                return *line;
+            }
+        }
+        0
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
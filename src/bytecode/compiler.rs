@@ -0,0 +1,210 @@
+use crate::errors::err::{self, RuntimeErr};
+use crate::parsing::ast::{Ast, ExprVisitor, Program, StmtVisitor};
+use crate::parsing::expressions::{Binary, Call, Grouping, Literal, Unary, Value, Variable};
+use crate::parsing::statements::{Dec, ExprStmt, Print, Test};
+use crate::parsing::tokens::{Token, TokenType};
+
+use super::chunk::{Chunk, OpCode};
+
+/// turns a parsed program into a chunk, reporting errors like the parser does
+pub fn compile(program: &Program) -> Option<Chunk> {
+    let mut compiler = Compiler { chunk: Chunk::new() };
+    let ast = program.ast.as_ref();
+    let mut line = 1;
+    for s in program.statements.iter() {
+        if let Err(e) = ast.visit_stmt(*s, &mut compiler) {
+            err::error_at(e.token(), e.message());
+            return None;
+        }
+        line = ast.stmt(*s).line();
+    }
+    compiler.chunk.write_op(OpCode::Return, line);
+    Some(compiler.chunk)
+}
+
+struct Compiler {
+    chunk: Chunk,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: OpCode, line: u32) {
+        self.chunk.write_op(op, line);
+    }
+
+    fn emit_constant(&mut self, op: OpCode, value: Value, token: &Token) -> Result<(), RuntimeErr> {
+        let chunk = &mut self.chunk;
+        let index = match chunk.add_constant(value) {
+            Some(i) => i,
+            None => return Err(RuntimeErr::new("Too many constants in one chunk.".to_owned(), token.clone())),
+        };
+        chunk.write_op(op, token.line);
+        for byte in index.to_be_bytes() {
+            chunk.write(byte, token.line);
+        }
+        Ok(())
+    }
+}
+
+impl ExprVisitor for Compiler {
+    type Output = Result<(), RuntimeErr>;
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) -> Result<(), RuntimeErr> {
+        ast.visit_expr(b.left, self)?;
+        ast.visit_expr(b.right, self)?;
+        let op = match b.operator.variant {
+            TokenType::PLUS => OpCode::Add,
+            TokenType::MINUS => OpCode::Subtract,
+            TokenType::STAR => OpCode::Multiply,
+            TokenType::SLASH => OpCode::Divide,
+            TokenType::GREATER => OpCode::Greater,
+            TokenType::GREATER_EQUAL => OpCode::GreaterEqual,
+            TokenType::LESS => OpCode::Less,
+            TokenType::LESS_EQUAL => OpCode::LessEqual,
+            TokenType::EQUAL_EQUAL => OpCode::Equal,
+            TokenType::BANG_EQUAL => OpCode::NotEqual,
+            _ => return Err(RuntimeErr::new("Unknown binary operator.".to_owned(), b.operator.clone())),
+        };
+        self.emit(op, b.operator.line);
+        Ok(())
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) -> Result<(), RuntimeErr> {
+        ast.visit_expr(g.expr, self)
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, l: &Literal) -> Result<(), RuntimeErr> {
+        match &l.val {
+            Value::Nil => self.emit(OpCode::Nil, l.token.line),
+            Value::Bool(true) => self.emit(OpCode::True, l.token.line),
+            Value::Bool(false) => self.emit(OpCode::False, l.token.line),
+            v => self.emit_constant(OpCode::Constant, v.clone(), &l.token)?,
+        }
+        Ok(())
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) -> Result<(), RuntimeErr> {
+        ast.visit_expr(u.right, self)?;
+        let op = match u.operator.variant {
+            TokenType::BANG => OpCode::Not,
+            _ => OpCode::Negate,
+        };
+        self.emit(op, u.operator.line);
+        Ok(())
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, v: &Variable) -> Result<(), RuntimeErr> {
+        self.emit_constant(OpCode::GetGlobal, Value::S(v.symbol.as_str()), &v.name)?;
+        Ok(())
+    }
+
+    fn visit_call(&mut self, ast: &Ast, c: &Call) -> Result<(), RuntimeErr> {
+        ast.visit_expr(c.callee, self)?;
+        for a in c.arguments.iter() {
+            ast.visit_expr(*a, self)?;
+        }
+        self.emit(OpCode::Call, c.paren.line);
+        // the parser caps calls at 255 arguments
+        self.chunk.write(c.arguments.len() as u8, c.paren.line);
+        Ok(())
+    }
+}
+
+impl StmtVisitor for Compiler {
+    type Output = Result<(), RuntimeErr>;
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) -> Result<(), RuntimeErr> {
+        ast.visit_expr(s.expr, self)?;
+        self.emit(OpCode::Pop, s.line);
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) -> Result<(), RuntimeErr> {
+        ast.visit_expr(s.expr, self)?;
+        self.emit(OpCode::Print, s.line);
+        Ok(())
+    }
+
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) -> Result<(), RuntimeErr> {
+        ast.visit_expr(d.expr, self)?;
+        self.emit_constant(OpCode::DefineGlobal, Value::S(d.symbol.as_str()), &d.name)?;
+        Ok(())
+    }
+
+    // test blocks only run under `lox test`, which uses the tree-walker
+    fn visit_test(&mut self, _ast: &Ast, _t: &Test) -> Result<(), RuntimeErr> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::debug::disassemble_chunk;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+
+    fn chunk(source: &str) -> Chunk {
+        compile(&Parser::new(Scanner::new(source)).parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn emits_operands_in_order() {
+        let chunk = chunk("var a = 1;\nprint -a + 2 * 3 == nil;");
+        let expected = [
+            OpCode::Constant as u8, 0, 0,
+            OpCode::DefineGlobal as u8, 0, 1,
+            OpCode::GetGlobal as u8, 0, 2,
+            OpCode::Negate as u8,
+            OpCode::Constant as u8, 0, 3,
+            OpCode::Constant as u8, 0, 4,
+            OpCode::Multiply as u8,
+            OpCode::Add as u8,
+            OpCode::Nil as u8,
+            OpCode::Equal as u8,
+            OpCode::Print as u8,
+            OpCode::Return as u8,
+        ];
+        assert_eq!(chunk.code, expected);
+        assert_eq!(chunk.constants.iter().map(|c| c.to_string()).collect::<Vec<_>>(), vec!["1", "a", "a", "2", "3"]);
+        assert_eq!(chunk.lines, vec![(1, 6), (2, 16)]);
+    }
+
+    #[test]
+    fn calls_carry_their_argument_count() {
+        let chunk = chunk("assert_eq(true, !false);");
+        assert_eq!(
+            chunk.code,
+            [
+                OpCode::GetGlobal as u8, 0, 0,
+                OpCode::True as u8,
+                OpCode::False as u8,
+                OpCode::Not as u8,
+                OpCode::Call as u8, 2,
+                OpCode::Pop as u8,
+                OpCode::Return as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks_are_not_compiled() {
+        assert_eq!(chunk("test \"t\" { print 1; }").code, [OpCode::Return as u8]);
+    }
+
+    #[test]
+    fn disassembles_a_compiled_program() {
+        assert_eq!(
+            disassemble_chunk(&chunk("var a = \"x\";\nprint a;\nassert(true);"), "script"),
+            "== script ==\n\
+             0000    1 OP_CONSTANT         0 'x'\n\
+             0003    | OP_DEFINE_GLOBAL    1 'a'\n\
+             0006    2 OP_GET_GLOBAL       2 'a'\n\
+             0009    | OP_PRINT\n\
+             0010    3 OP_GET_GLOBAL       3 'assert'\n\
+             0013    | OP_TRUE\n\
+             0014    | OP_CALL             1\n\
+             0016    | OP_POP\n\
+             0017    | OP_RETURN\n"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use super::chunk::{Chunk, OpCode};
+
+/// the whole chunk in clox's disassembler format, headed by `name`
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
+    let mut out = format!("== {} ==\n", name);
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (text, next) = disassemble_instruction(chunk, offset);
+        out.push_str(&text);
+        out.push('\n');
+        offset = next;
+    }
+    out
+}
+
+/// one instruction as `offset line OP_NAME operands`, with the offset of the next one
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let line = chunk.line(offset);
+    let line = if offset > 0 && line == chunk.line(offset - 1) {
+        "   |".to_owned()
+    } else {
+        format!("{:4}", line)
+    };
+    let prefix = format!("{:04} {} ", offset, line);
+
+    let op = match OpCode::from_byte(chunk.code[offset]) {
+        Some(op) => op,
+        None => return (format!("{}Unknown opcode {}", prefix, chunk.code[offset]), offset + 1),
+    };
+    match op {
+        OpCode::Constant | OpCode::DefineGlobal | OpCode::GetGlobal => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = chunk.constants[index as usize].to_string();
+            (format!("{}{:<16} {:4} '{}'", prefix, name(op), index, constant), offset + 3)
+        }
+        OpCode::Call => (format!("{}{:<16} {:4}", prefix, name(op), chunk.code[offset + 1]), offset + 2),
+        _ => (format!("{}{}", prefix, name(op)), offset + 1),
+    }
+}
+
+fn name(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Constant => "OP_CONSTANT",
+        OpCode::Nil => "OP_NIL",
+        OpCode::True => "OP_TRUE",
+        OpCode::False => "OP_FALSE",
+        OpCode::Pop => "OP_POP",
+        OpCode::DefineGlobal => "OP_DEFINE_GLOBAL",
+        OpCode::GetGlobal => "OP_GET_GLOBAL",
+        OpCode::Equal => "OP_EQUAL",
+        OpCode::NotEqual => "OP_NOT_EQUAL",
+        OpCode::Greater => "OP_GREATER",
+        OpCode::GreaterEqual => "OP_GREATER_EQUAL",
+        OpCode::Less => "OP_LESS",
+        OpCode::LessEqual => "OP_LESS_EQUAL",
+        OpCode::Add => "OP_ADD",
+        OpCode::Subtract => "OP_SUBTRACT",
+        OpCode::Multiply => "OP_MULTIPLY",
+        OpCode::Divide => "OP_DIVIDE",
+        OpCode::Not => "OP_NOT",
+        OpCode::Negate => "OP_NEGATE",
+        OpCode::Print => "OP_PRINT",
+        OpCode::Call => "OP_CALL",
+        OpCode::Return => "OP_RETURN",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_opcodes_are_shown_and_skipped() {
+        let mut chunk = Chunk::new();
+        chunk.write(200, 4);
+        chunk.write_op(OpCode::Return, 5);
+        assert_eq!(disassemble_instruction(&chunk, 0), ("0000    4 Unknown opcode 200".to_owned(), 1));
+        assert_eq!(disassemble_instruction(&chunk, 1), ("0001    5 OP_RETURN".to_owned(), 2));
+    }
+}
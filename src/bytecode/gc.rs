@@ -0,0 +1,227 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use crate::parsing::expressions::NativeFn;
+
+// first collection happens once this many bytes are live, later ones at twice what survived
+const INITIAL_THRESHOLD: usize = 1024 * 1024;
+
+/// handle to an object owned by the `Heap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+pub enum Obj {
+    Str(String),
+    Native(NativeFn),
+}
+
+impl Obj {
+    fn size(&self) -> usize {
+        match self {
+            Obj::Str(s) => mem::size_of::<Obj>() + s.capacity(),
+            Obj::Native(_) => mem::size_of::<Obj>(),
+        }
+    }
+
+    /// pushes the objects this one refers to
+    fn trace(&self, _gray: &mut Vec<ObjRef>) {
+        match self {
+            Obj::Str(_) | Obj::Native(_) => {}
+        }
+    }
+}
+
+struct Entry {
+    obj: Obj,
+    marked: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub collections: u64,
+    pub allocated: u64,
+    pub freed: u64,
+    pub live: usize,
+    pub bytes: usize,
+}
+
+/// mark-and-sweep collected storage for everything the VM allocates
+pub struct Heap {
+    entries: Vec<Option<Entry>>,
+    free: Vec<usize>,
+    // every live string by the hash of its text, so equal strings share one object; the text itself
+    // stays in the entry. Entries don't keep strings alive
+    strings: HashMap<u64, Vec<ObjRef>>,
+    next_gc: usize,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap { entries: Vec::new(), free: Vec::new(), strings: HashMap::new(), next_gc: INITIAL_THRESHOLD, stats: GcStats::default() }
+    }
+
+    /// the caller should collect before allocating when this is true
+    pub fn should_collect(&self) -> bool {
+        self.stats.bytes > self.next_gc
+    }
+
+    /// the existing object for `s`, if that text is already on the heap
+    pub fn find_string(&self, s: &str) -> Option<ObjRef> {
+        let candidates = self.strings.get(&hash(s))?;
+        candidates.iter().copied().find(|r| matches!(self.get(*r), Obj::Str(text) if text == s))
+    }
+
+    pub fn alloc(&mut self, obj: Obj) -> ObjRef {
+        let interned = match &obj {
+            Obj::Str(s) => Some(hash(s)),
+            Obj::Native(_) => None,
+        };
+        let r = self.insert(obj);
+        if let Some(h) = interned {
+            self.strings.entry(h).or_default().push(r);
+        }
+        r
+    }
+
+    fn insert(&mut self, obj: Obj) -> ObjRef {
+        self.stats.allocated += 1;
+        self.stats.live += 1;
+        self.stats.bytes += obj.size();
+        let entry = Some(Entry { obj, marked: false });
+        match self.free.pop() {
+            Some(i) => {
+                self.entries[i] = entry;
+                ObjRef(i)
+            }
+            None => {
+                self.entries.push(entry);
+                ObjRef(self.entries.len() - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, r: ObjRef) -> &Obj {
+        match &self.entries[r.0] {
+            Some(entry) => &entry.obj,
+            None => panic!("use of collected object {:?}", r),
+        }
+    }
+
+    /// frees everything not reachable from `roots`, returning how many objects went
+    pub fn collect(&mut self, roots: impl Iterator<Item = ObjRef>) -> usize {
+        let mut gray: Vec<ObjRef> = roots.collect();
+        while let Some(r) = gray.pop() {
+            let entry = self.entries[r.0].as_mut().unwrap();
+            if entry.marked {
+                continue;
+            }
+            entry.marked = true;
+            entry.obj.trace(&mut gray);
+        }
+
+        let mut freed = 0;
+        for (i, slot) in self.entries.iter_mut().enumerate() {
+            match slot {
+                Some(entry) if entry.marked => entry.marked = false,
+                Some(entry) => {
+                    // only this object's own entry goes, another object with the same text stays interned
+                    if let Obj::Str(s) = &entry.obj {
+                        let h = hash(s);
+                        if let Some(refs) = self.strings.get_mut(&h) {
+                            refs.retain(|r| r.0 != i);
+                            if refs.is_empty() {
+                                self.strings.remove(&h);
+                            }
+                        }
+                    }
+                    self.stats.bytes -= entry.obj.size();
+                    *slot = None;
+                    self.free.push(i);
+                    freed += 1;
+                }
+                None => {}
+            }
+        }
+
+        self.stats.collections += 1;
+        self.stats.freed += freed as u64;
+        self.stats.live -= freed;
+        self.next_gc = (self.stats.bytes * 2).max(INITIAL_THRESHOLD);
+        freed
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+}
+
+fn hash(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Heap, Obj};
+    use crate::runtime::natives::natives;
+
+    #[test]
+    fn frees_unreachable_strings_and_natives() {
+        let mut heap = Heap::new();
+        let kept = heap.alloc(Obj::Str("kept".to_owned()));
+        heap.alloc(Obj::Str("dropped".to_owned()));
+        heap.alloc(Obj::Native(natives().remove(0)));
+        let native = heap.alloc(Obj::Native(natives().remove(0)));
+
+        assert_eq!(heap.collect([kept, native].into_iter()), 2);
+        assert!(matches!(heap.get(kept), Obj::Str(s) if s == "kept"));
+        assert!(matches!(heap.get(native), Obj::Native(_)));
+        assert_eq!(heap.find_string("kept"), Some(kept));
+        assert_eq!(heap.find_string("dropped"), None);
+
+        let stats = heap.stats();
+        assert_eq!((stats.collections, stats.allocated, stats.freed, stats.live), (1, 4, 2, 2));
+    }
+
+    #[test]
+    fn survivors_are_unmarked_for_the_next_collection() {
+        let mut heap = Heap::new();
+        let s = heap.alloc(Obj::Str("s".to_owned()));
+        assert_eq!(heap.collect([s].into_iter()), 0);
+        assert_eq!(heap.collect([].into_iter()), 1);
+        assert_eq!(heap.stats().bytes, 0);
+    }
+
+    #[test]
+    fn freed_slots_are_reused() {
+        let mut heap = Heap::new();
+        let first = heap.alloc(Obj::Str("first".to_owned()));
+        heap.collect([].into_iter());
+        let second = heap.alloc(Obj::Str("second".to_owned()));
+        assert_eq!(first, second);
+        assert!(matches!(heap.get(second), Obj::Str(s) if s == "second"));
+    }
+
+    #[test]
+    fn freeing_a_duplicate_keeps_the_live_string_interned() {
+        let mut heap = Heap::new();
+        heap.alloc(Obj::Str("s".to_owned()));
+        let newer = heap.alloc(Obj::Str("s".to_owned()));
+        assert_eq!(heap.collect([newer].into_iter()), 1);
+        assert_eq!(heap.find_string("s"), Some(newer));
+
+        assert_eq!(heap.collect([].into_iter()), 1);
+        assert_eq!(heap.find_string("s"), None);
+        assert!(heap.strings.is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use std::rc::Rc;
+
+use super::chunk::{Chunk, OpCode};
+use crate::parsing::expressions::Value;
+
+const MAGIC: &[u8; 4] = b"LOXC";
+/// bumped whenever the opcode set or the layout below changes
+pub const VERSION: u16 = 1;
+
+// header: magic, version (u16), source checksum (u64), payload checksum (u64)
+// payload: constants, line runs, code, each prefixed with a u32 count; all integers little-endian
+const HEADER_LEN: usize = 4 + 2 + 8 + 8;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_BOOL: u8 = 2;
+const TAG_NIL: u8 = 3;
+
+/// a loaded `.loxc` file
+pub struct Compiled {
+    pub chunk: Chunk,
+    pub source_checksum: u64,
+}
+
+/// FNV-1a, used both for the source checksum and to detect corrupt payloads
+pub fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn serialize(chunk: &Chunk, source: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend((chunk.constants.len() as u32).to_le_bytes());
+    for constant in chunk.constants.iter() {
+        match constant {
+            Value::Int(n) => {
+                payload.push(TAG_NUMBER);
+                payload.extend(n.to_le_bytes());
+            }
+            Value::S(s) => {
+                payload.push(TAG_STRING);
+                payload.extend((s.len() as u32).to_le_bytes());
+                payload.extend(s.as_bytes());
+            }
+            Value::Bool(b) => {
+                payload.push(TAG_BOOL);
+                payload.push(*b as u8);
+            }
+            // natives are looked up by name at runtime and never become constants
+            Value::Native(_) | Value::Nil => payload.push(TAG_NIL),
+        }
+    }
+    payload.extend((chunk.lines.len() as u32).to_le_bytes());
+    for (line, count) in chunk.lines.iter() {
+        payload.extend(line.to_le_bytes());
+        payload.extend((*count as u32).to_le_bytes());
+    }
+    payload.extend((chunk.code.len() as u32).to_le_bytes());
+    payload.extend(chunk.code.iter());
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.extend(checksum(source.as_bytes()).to_le_bytes());
+    out.extend(checksum(&payload).to_le_bytes());
+    out.extend(payload);
+    out
+}
+
+pub fn deserialize(bytes: &[u8]) -> Result<Compiled, String> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err("not a .loxc file".to_owned());
+    }
+    let mut header = Reader { bytes, at: 4 };
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(format!("compiled for format version {}, this lox reads version {}", version, VERSION));
+    }
+    let source_checksum = header.u64()?;
+    let payload_checksum = header.u64()?;
+    if checksum(&bytes[HEADER_LEN..]) != payload_checksum {
+        return Err("checksum mismatch, the file is corrupt".to_owned());
+    }
+
+    let mut r = Reader { bytes, at: HEADER_LEN };
+    let mut chunk = Chunk::new();
+    for _ in 0..r.u32()? {
+        let constant = match r.u8()? {
+            TAG_NUMBER => Value::Int(f64::from_le_bytes(r.take(8)?.try_into().unwrap())),
+            TAG_STRING => {
+                let len = r.u32()? as usize;
+                match String::from_utf8(r.take(len)?.to_vec()) {
+                    Ok(s) => Value::S(Rc::from(s)),
+                    Err(_) => return Err("string constant is not valid UTF-8".to_owned()),
+                }
+            }
+            TAG_BOOL => Value::Bool(r.u8()? != 0),
+            TAG_NIL => Value::Nil,
+            tag => return Err(format!("unknown constant tag {}", tag)),
+        };
+        chunk.constants.push(constant);
+    }
+    for _ in 0..r.u32()? {
+        let line = r.u32()?;
+        let count = r.u32()? as usize;
+        chunk.lines.push((line, count));
+    }
+    let len = r.u32()? as usize;
+    chunk.code = r.take(len)?.to_vec();
+    if r.at != bytes.len() {
+        return Err("trailing bytes after the code".to_owned());
+    }
+
+    verify(&chunk)?;
+    Ok(Compiled { chunk, source_checksum })
+}
+
+// checks every instruction decodes, refers to constants that exist and finds enough values on the stack,
+// so the VM never indexes out of bounds. The code has no jumps, so one pass sees every path
+fn verify(chunk: &Chunk) -> Result<(), String> {
+    if chunk.lines.iter().map(|(_, count)| count).sum::<usize>() != chunk.code.len() {
+        return Err("line table does not cover the code".to_owned());
+    }
+    let mut offset = 0;
+    let mut depth = 0;
+    let mut last = None;
+    while offset < chunk.code.len() {
+        let op = match OpCode::from_byte(chunk.code[offset]) {
+            Some(op) => op,
+            None => return Err(format!("unknown opcode {} at offset {}", chunk.code[offset], offset)),
+        };
+        let operands = match op {
+            OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal => 2,
+            OpCode::Call => 1,
+            _ => 0,
+        };
+        if offset + operands >= chunk.code.len() && op != OpCode::Return {
+            return Err(format!("truncated instruction at offset {}", offset));
+        }
+        if operands == 2 {
+            let index = chunk.read_u16(offset + 1) as usize;
+            match (op, chunk.constants.get(index)) {
+                (_, None) => return Err(format!("constant {} out of range at offset {}", index, offset)),
+                (OpCode::GetGlobal | OpCode::DefineGlobal, Some(Value::S(_))) | (OpCode::Constant, Some(_)) => {}
+                _ => return Err(format!("variable name is not a string at offset {}", offset)),
+            }
+        }
+
+        // how many values the instruction pops, and how many it pushes back
+        let (pops, pushes) = match op {
+            OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False | OpCode::GetGlobal => (0, 1),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print => (1, 0),
+            OpCode::Not | OpCode::Negate => (1, 1),
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => (2, 1),
+            // the arguments and the callee
+            OpCode::Call => (chunk.code[offset + 1] as usize + 1, 1),
+            OpCode::Return => (0, 0),
+        };
+        if pops > depth {
+            return Err(format!("stack underflow at offset {}", offset));
+        }
+        depth = depth - pops + pushes;
+
+        last = Some(op);
+        offset += 1 + operands;
+    }
+    if last != Some(OpCode::Return) {
+        return Err("code does not end with a return".to_owned());
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.at < n {
+            return Err("unexpected end of file".to_owned());
+        }
+        self.at += n;
+        Ok(&self.bytes[self.at - n..self.at])
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, deserialize, serialize, HEADER_LEN};
+    use crate::bytecode::chunk::{Chunk, OpCode};
+    use crate::bytecode::compiler::compile;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+
+    fn compiled(source: &str) -> Vec<u8> {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        serialize(&compile(&program).unwrap(), source)
+    }
+
+    // a file holding `code` with every checksum right, as if written by a broken or hostile compiler
+    fn forged(code: &[u8]) -> Vec<u8> {
+        let mut chunk = Chunk::new();
+        for byte in code {
+            chunk.write(*byte, 1);
+        }
+        serialize(&chunk, "")
+    }
+
+    #[test]
+    fn round_trips() {
+        let source = "var a = \"s\";\nprint a + \"t\";\nprint 1 < 2;\n";
+        let bytes = compiled(source);
+        let loaded = deserialize(&bytes).unwrap();
+        assert_eq!(serialize(&loaded.chunk, source), bytes);
+        assert_eq!(loaded.source_checksum, checksum(source.as_bytes()));
+    }
+
+    #[test]
+    fn rejects_a_corrupt_payload() {
+        let mut bytes = compiled("print 1;");
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert_eq!(deserialize(&bytes).err().unwrap(), "checksum mismatch, the file is corrupt");
+    }
+
+    #[test]
+    fn rejects_a_truncated_file() {
+        let bytes = compiled("print 1;");
+        assert_eq!(deserialize(&bytes[..HEADER_LEN - 1]).err().unwrap(), "not a .loxc file");
+        assert!(deserialize(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_every_truncation() {
+        let bytes = compiled("var a = \"s\";\nprint a;");
+        for len in 0..bytes.len() {
+            assert!(deserialize(&bytes[..len]).is_err(), "{} of {} bytes", len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn rejects_any_flipped_byte() {
+        let bytes = compiled("var a = \"s\";\nprint a;");
+        // the source checksum only tells whether the file is stale, so a flip there still loads
+        let source_checksum = 6..14;
+        for i in (0..bytes.len()).filter(|i| !source_checksum.contains(i)) {
+            let mut flipped = bytes.clone();
+            flipped[i] ^= 0x01;
+            assert!(deserialize(&flipped).is_err(), "byte {} flipped", i);
+        }
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum() {
+        let mut bytes = compiled("print 1;");
+        bytes[HEADER_LEN - 1] ^= 0x80;
+        assert_eq!(deserialize(&bytes).err().unwrap(), "checksum mismatch, the file is corrupt");
+    }
+
+    #[test]
+    fn rejects_another_version() {
+        let mut bytes = compiled("print 1;");
+        bytes[4] = bytes[4].wrapping_add(1);
+        assert!(deserialize(&bytes).err().unwrap().starts_with("compiled for format version"));
+    }
+
+    #[test]
+    fn rejects_stack_underflow() {
+        let call = forged(&[OpCode::Call as u8, 5, OpCode::Return as u8]);
+        assert_eq!(deserialize(&call).err().unwrap(), "stack underflow at offset 0");
+        let print = forged(&[OpCode::Nil as u8, OpCode::Add as u8, OpCode::Return as u8]);
+        assert_eq!(deserialize(&print).err().unwrap(), "stack underflow at offset 1");
+    }
+
+    #[test]
+    fn rejects_a_call_with_more_arguments_than_values() {
+        let call = forged(&[OpCode::Nil as u8, OpCode::Nil as u8, OpCode::Call as u8, 2, OpCode::Return as u8]);
+        assert_eq!(deserialize(&call).err().unwrap(), "stack underflow at offset 2");
+        let call = forged(&[OpCode::Nil as u8, OpCode::Nil as u8, OpCode::Call as u8, 1, OpCode::Return as u8]);
+        assert!(deserialize(&call).is_ok());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod chunk;
+pub mod compiler;
+pub mod debug;
+pub mod gc;
+pub mod loxc;
+pub mod vm;
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::errors::err::RuntimeErr;
+use crate::parsing::expressions::Value;
+use crate::parsing::tokens::{Token, TokenType};
+use crate::runtime::interpreter::{Frame, DEFAULT_MAX_CALL_DEPTH};
+use crate::runtime::natives::natives;
+
+use super::chunk::{Chunk, OpCode};
+use super::debug::disassemble_instruction;
+use super::gc::{GcStats, Heap, Obj, ObjRef};
+
+/// a value on the VM stack; anything bigger than a word lives on the `Heap`
+#[derive(Debug, Clone, Copy)]
+enum Val {
+    Number(f64),
+    Bool(bool),
+    Nil,
+    Obj(ObjRef),
+}
+
+/// stack machine running a chunk with the same semantics as the tree-walking `Interpreter`
+pub struct VM {
+    stack: Vec<Val>,
+    // keyed by the interned name string
+    globals: HashMap<ObjRef, Val>,
+    // the running chunk's constants, moved onto the heap when it starts
+    constants: Vec<Val>,
+    heap: Heap,
+    output: Box<dyn Write>,
+    // dump the stack and each instruction to stderr as it runs
+    trace_exec: bool,
+    // active calls, the script's first, kept like the tree-walker's for the stack overflow trace
+    frames: Vec<Frame>,
+    max_call_depth: usize,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
+}
+
+impl VM {
+    pub fn new() -> Self {
+        let mut heap = Heap::new();
+        let mut globals = HashMap::new();
+        for native in natives() {
+            let name = heap.alloc(Obj::Str(native.name.to_owned()));
+            globals.insert(name, Val::Obj(heap.alloc(Obj::Native(native))));
+        }
+        VM {
+            stack: Vec::new(),
+            globals,
+            constants: Vec::new(),
+            heap,
+            output: Box::new(io::stdout()),
+            trace_exec: false,
+            frames: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+
+    pub fn set_trace_exec(&mut self, trace_exec: bool) {
+        self.trace_exec = trace_exec;
+    }
+
+    /// the most frames, the script's included, that may be active at once
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    pub fn run(&mut self, chunk: &Chunk) -> Result<(), RuntimeErr> {
+        self.constants = Vec::new();
+        for constant in chunk.constants.iter() {
+            let val = self.import(constant.clone());
+            self.constants.push(val);
+        }
+
+        self.frames = vec![Frame { name: "script".to_owned(), line: 0 }];
+        let mut ip = 0;
+        loop {
+            let offset = ip;
+            if self.trace_exec {
+                let stack: String = self.stack.iter().map(|v| format!("[ {} ]", self.display(*v))).collect();
+                eprintln!("          {}", stack);
+                eprintln!("{}", disassemble_instruction(chunk, offset).0);
+            }
+            let op = match OpCode::from_byte(chunk.code[ip]) {
+                Some(op) => op,
+                None => return Err(self.error(chunk, offset, format!("Unknown opcode {}.", chunk.code[ip]))),
+            };
+            ip += 1;
+
+            match op {
+                OpCode::Constant => {
+                    let index = chunk.read_u16(ip);
+                    ip += 2;
+                    self.stack.push(self.constants[index as usize]);
+                }
+                OpCode::Nil => self.stack.push(Val::Nil),
+                OpCode::True => self.stack.push(Val::Bool(true)),
+                OpCode::False => self.stack.push(Val::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.name(chunk.read_u16(ip));
+                    ip += 2;
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.name(chunk.read_u16(ip));
+                    ip += 2;
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(*value),
+                        None => {
+                            let message = format!("Undefined variable '{}'.", self.display(Val::Obj(name)));
+                            return Err(self.error(chunk, offset, message));
+                        }
+                    }
+                }
+                OpCode::Equal | OpCode::NotEqual => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(Val::Bool(self.equal(a, b) == (op == OpCode::Equal)));
+                }
+                OpCode::Add => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let result = match (a, b) {
+                        (Val::Number(x), Val::Number(y)) => Val::Number(x + y),
+                        (Val::Obj(x), Val::Obj(y)) => match (self.heap.get(x), self.heap.get(y)) {
+                            (Obj::Str(x), Obj::Str(y)) => {
+                                let s = x.clone() + y;
+                                Val::Obj(self.string(s))
+                            }
+                            _ => return Err(self.error(chunk, offset, "invalid arguments to binary operation".to_owned())),
+                        },
+                        _ => return Err(self.error(chunk, offset, "invalid arguments to binary operation".to_owned())),
+                    };
+                    self.stack.push(result);
+                }
+                OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let (x, y) = match (a, b) {
+                        (Val::Number(x), Val::Number(y)) => (x, y),
+                        _ => return Err(self.error(chunk, offset, "invalid arguments to binary operation".to_owned())),
+                    };
+                    let result = match op {
+                        OpCode::Subtract => Val::Number(x - y),
+                        OpCode::Multiply => Val::Number(x * y),
+                        OpCode::Divide => Val::Number(x / y),
+                        OpCode::Greater => Val::Bool(x > y),
+                        OpCode::GreaterEqual => Val::Bool(x >= y),
+                        OpCode::Less => Val::Bool(x < y),
+                        _ => Val::Bool(x <= y),
+                    };
+                    self.stack.push(result);
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Val::Bool(matches!(value, Val::Nil | Val::Bool(false))));
+                }
+                OpCode::Negate => {
+                    // like the tree-walker, negating a non-number gives nil
+                    let value = match self.pop() {
+                        Val::Number(n) => Val::Number(-n),
+                        _ => Val::Nil,
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = self.display(value);
+                    _ = writeln!(self.output, "{}", text);
+                }
+                OpCode::Call => {
+                    let argc = chunk.code[ip] as usize;
+                    ip += 1;
+                    // verified `.loxc` files can't get here, but a hand-built chunk could
+                    if argc >= self.stack.len() {
+                        return Err(self.error(chunk, offset, "Not enough values on the stack for the call.".to_owned()));
+                    }
+                    let arguments: Vec<Value> = self.stack[self.stack.len() - argc..].iter().map(|v| self.export(*v)).collect();
+                    self.stack.truncate(self.stack.len() - argc);
+                    let native = match self.pop() {
+                        Val::Obj(r) => match self.heap.get(r) {
+                            Obj::Native(native) => native.clone(),
+                            _ => return Err(self.error(chunk, offset, "Can only call functions and classes.".to_owned())),
+                        },
+                        _ => return Err(self.error(chunk, offset, "Can only call functions and classes.".to_owned())),
+                    };
+                    if argc != native.arity {
+                        let message = format!("Expected {} arguments but got {}.", native.arity, argc);
+                        return Err(self.error(chunk, offset, message));
+                    }
+                    if let Err(message) = self.enter_call(native.name, chunk.line(offset)) {
+                        return Err(self.error(chunk, offset, message));
+                    }
+                    // the native itself can't reach the heap, so the VM does the collecting
+                    let res = match native.name {
+                        "gc" => Ok(Value::Int(self.collect() as f64)),
+                        _ => (native.func)(&arguments),
+                    };
+                    self.frames.pop();
+                    match res {
+                        Ok(v) => {
+                            let v = self.import(v);
+                            self.stack.push(v);
+                        }
+                        Err(message) => return Err(self.error(chunk, offset, message)),
+                    }
+                }
+                OpCode::Return => return Ok(()),
+            }
+        }
+    }
+
+    /// pushes a frame for a call made on `line`, or gives the stack overflow message once
+    /// `max_call_depth` frames are active
+    fn enter_call(&mut self, name: &str, line: u32) -> Result<(), String> {
+        if let Some(caller) = self.frames.last_mut() {
+            caller.line = line;
+        }
+        if self.frames.len() >= self.max_call_depth {
+            let mut message = "Stack overflow.".to_owned();
+            for frame in self.frames.iter().rev() {
+                message.push_str(&format!("\n[line {}] in {}", frame.line, frame.name));
+            }
+            return Err(message);
+        }
+        self.frames.push(Frame { name: name.to_owned(), line });
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Val {
+        self.stack.pop().unwrap_or(Val::Nil)
+    }
+
+    fn alloc(&mut self, obj: Obj) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect();
+        }
+        self.heap.alloc(obj)
+    }
+
+    fn collect(&mut self) -> usize {
+        let roots = self.stack.iter().chain(self.globals.values()).chain(self.constants.iter());
+        let mut roots: Vec<ObjRef> = roots
+            .filter_map(|v| match v {
+                Val::Obj(r) => Some(*r),
+                _ => None,
+            })
+            .collect();
+        roots.extend(self.globals.keys());
+        self.heap.collect(roots.into_iter())
+    }
+
+    fn equal(&self, a: Val, b: Val) -> bool {
+        match (a, b) {
+            (Val::Number(x), Val::Number(y)) => x == y,
+            (Val::Bool(x), Val::Bool(y)) => x == y,
+            (Val::Nil, Val::Nil) => true,
+            // strings are interned, so the same text is always the same object
+            (Val::Obj(x), Val::Obj(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    /// moves a value onto the VM's heap
+    fn import(&mut self, value: Value) -> Val {
+        match value {
+            Value::Int(n) => Val::Number(n),
+            Value::Bool(b) => Val::Bool(b),
+            Value::Nil => Val::Nil,
+            Value::S(s) => Val::Obj(self.string(s.to_string())),
+            Value::Native(native) => Val::Obj(self.alloc(Obj::Native(native))),
+        }
+    }
+
+    /// copies a value off the heap, for printing and for natives
+    fn export(&self, value: Val) -> Value {
+        match value {
+            Val::Number(n) => Value::Int(n),
+            Val::Bool(b) => Value::Bool(b),
+            Val::Nil => Value::Nil,
+            Val::Obj(r) => match self.heap.get(r) {
+                Obj::Str(s) => Value::S(Rc::from(s.as_str())),
+                Obj::Native(native) => Value::Native(native.clone()),
+            },
+        }
+    }
+
+    /// the interned string, reusing the heap's copy when there is one
+    fn string(&mut self, s: String) -> ObjRef {
+        match self.heap.find_string(&s) {
+            Some(r) => r,
+            None => self.alloc(Obj::Str(s)),
+        }
+    }
+
+    // global instructions name their variable with a string constant, checked when loading `.loxc` files
+    fn name(&self, index: u16) -> ObjRef {
+        match self.constants[index as usize] {
+            Val::Obj(r) => r,
+            _ => unreachable!("global name is not a string constant"),
+        }
+    }
+
+    /// text as `print` shows it, without copying it into a `Value`
+    fn display(&self, value: Val) -> String {
+        match value {
+            Val::Obj(r) => match self.heap.get(r) {
+                Obj::Str(s) => s.clone(),
+                Obj::Native(_) => "<native fn>".to_owned(),
+            },
+            v => self.export(v).to_string(),
+        }
+    }
+
+    fn error(&mut self, chunk: &Chunk, offset: usize, message: String) -> RuntimeErr {
+        self.stack.clear();
+        RuntimeErr::new(message, Token::new(TokenType::IDENTIFIER, "".to_owned(), chunk.line(offset)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::VM;
+    use crate::bytecode::chunk::{Chunk, OpCode};
+    use crate::bytecode::compiler::compile;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::runtime::interpreter::Interpreter;
+
+    // compiled without the optimizer, so concatenations happen at run time
+    fn run(source: &str) -> VM {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        let mut vm = VM::new();
+        vm.output = Box::new(io::sink());
+        vm.run(&compile(&program).unwrap()).unwrap();
+        vm
+    }
+
+    #[test]
+    fn the_call_depth_is_limited_like_the_tree_walker() {
+        let source = "print 1;\nassert(true);\n";
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+
+        let mut vm = VM::new();
+        vm.output = Box::new(io::sink());
+        vm.set_max_call_depth(1);
+        let vm_error = vm.run(&compile(&program).unwrap()).err().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        interpreter.set_max_call_depth(1);
+        let tree_error = interpreter.run(&program).err().unwrap();
+
+        assert_eq!(vm_error.to_string(), "Stack overflow.\n[line 2] in script\n[line 2]");
+        assert_eq!(vm_error.to_string(), tree_error.to_string());
+
+        let mut vm = VM::new();
+        vm.output = Box::new(io::sink());
+        vm.set_max_call_depth(2);
+        assert!(vm.run(&compile(&program).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn gc_frees_unreachable_strings() {
+        let vm = run("var kept = \"a\" + \"b\";\nprint \"c\" + \"d\";\ngc();\n");
+        assert!(vm.heap.find_string("ab").is_some());
+        assert!(vm.heap.find_string("cd").is_none());
+        assert_eq!(vm.gc_stats().freed, 1);
+    }
+
+    #[test]
+    fn gc_keeps_constants_and_natives() {
+        let vm = run("gc();\n");
+        assert_eq!(vm.gc_stats().freed, 0);
+        for name in ["assert", "assert_eq", "gc"] {
+            assert!(vm.heap.find_string(name).is_some());
+        }
+    }
+
+    #[test]
+    fn a_call_without_enough_values_is_an_error() {
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::Call, 3);
+        chunk.write(5, 3);
+        chunk.write_op(OpCode::Return, 3);
+        let error = VM::new().run(&chunk).err().unwrap();
+        assert_eq!(error.to_string(), "Not enough values on the stack for the call.\n[line 3]");
+    }
+}
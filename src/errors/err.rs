@@ -1,11 +1,27 @@
-use super::super::parsing::tokens::Token;
+use super::super::parsing::tokens::{Token, TokenType};
 
 pub fn error(line: u32, message: &String) {
     report(line, &"".to_owned(), message);
 }
 
+pub fn error_at(token: &Token, message: &String) {
+    if token.variant == TokenType::EOF {
+        report(token.line, &" at end".to_owned(), message);
+    } else {
+        report(token.line, &format!(" at '{}'", token.lexeme), message);
+    }
+}
+
 fn report(line: u32, location: &String, message: &String) {
-    eprintln!("[Line {}] ERROR{}: {}", line, location, message);
+    eprintln!("[line {}] Error{}: {}", line, location, message);
+}
+
+/// a scan or parse error, kept so tools can show it somewhere other than stderr
+#[derive(Debug, Clone)]
+pub struct SyntaxErr {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
 }
 
 #[derive(Debug)]
@@ -15,11 +31,21 @@ pub struct RuntimeErr {
 }
 
 impl RuntimeErr {
-    pub fn default() -> Self {
-        RuntimeErr { message: "ERR".to_owned(), token: Token { variant: crate::parsing::tokens::TokenType::BANG, lexeme: "!".to_owned(), string: None, int: None, line: 1 } }
-    }
-
     pub fn new(message: String, token: Token) -> Self {
         RuntimeErr { message, token }
     }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+}
+
+impl std::fmt::Display for RuntimeErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n[line {}]", self.message, self.token.line)
+    }
 }
@@ -0,0 +1,5 @@
+pub mod bytecode;
+pub mod errors;
+pub mod parsing;
+pub mod runtime;
+pub mod tools;
@@ -1,28 +1,170 @@
-mod errors;
-mod parsing;
-mod runtime;
+use rust_lox_interpreter::bytecode::chunk::Chunk;
+use rust_lox_interpreter::bytecode::{compiler, loxc};
+use rust_lox_interpreter::errors::err::RuntimeErr;
+use rust_lox_interpreter::bytecode::vm::VM;
+use rust_lox_interpreter::parsing::{checker, optimizer, scanner, parser};
+use rust_lox_interpreter::parsing::tokens::{RawToken, TokenType};
+use rust_lox_interpreter::runtime::interpreter;
+use rust_lox_interpreter::runtime::coverage::Coverage;
+use rust_lox_interpreter::runtime::profiler::Profiler;
+use rust_lox_interpreter::runtime::tracer::Tracer;
+use rust_lox_interpreter::tools::{self, highlight};
 
-use parsing::{scanner, parser};
-use runtime::interpreter;
+#[derive(Default, PartialEq)]
+enum Backend {
+    #[default]
+    Tree,
+    VM,
+}
+
+/// flags accepted by `lox run`
+#[derive(Default)]
+struct RunOptions {
+    backend: Backend,
+    profile: bool,
+    folded: Option<String>,
+    coverage: Option<String>,
+    trace: bool,
+    trace_file: Option<String>,
+    trace_exec: bool,
+    gc_stats: bool,
+    max_call_depth: Option<usize>,
+}
+
+impl RunOptions {
+    /// splits `lox run` arguments into options and the script path
+    fn parse(args: &[String]) -> Result<(RunOptions, String), String> {
+        let mut options = RunOptions::default();
+        let mut path = None;
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--profile" => options.profile = true,
+                "--folded" => {
+                    i += 1;
+                    options.profile = true;
+                    options.folded = Some(args.get(i).ok_or("--folded expects a file")?.clone());
+                }
+                "--coverage" => {
+                    i += 1;
+                    options.coverage = Some(args.get(i).ok_or("--coverage expects a file")?.clone());
+                }
+                "--backend=tree" => options.backend = Backend::Tree,
+                "--backend=vm" => options.backend = Backend::VM,
+                "--trace-exec" => {
+                    options.backend = Backend::VM;
+                    options.trace_exec = true;
+                }
+                "--gc-stats" => {
+                    options.backend = Backend::VM;
+                    options.gc_stats = true;
+                }
+                "--max-call-depth" => {
+                    i += 1;
+                    let depth = args.get(i).and_then(|d| d.parse().ok()).ok_or("--max-call-depth expects a number")?;
+                    options.max_call_depth = Some(depth);
+                }
+                "--trace" => options.trace = true,
+                "--trace-file" => {
+                    i += 1;
+                    options.trace = true;
+                    options.trace_file = Some(args.get(i).ok_or("--trace-file expects a file")?.clone());
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+                _ if path.is_some() => return Err("expected a single script".to_owned()),
+                file => path = Some(file.to_owned()),
+            }
+            i += 1;
+        }
+        // precompiled bytecode can only run on the VM
+        if path.as_ref().is_some_and(|p: &String| p.ends_with(".loxc")) {
+            options.backend = Backend::VM;
+        }
+        let tree_only = options.profile || options.coverage.is_some() || options.trace;
+        if options.backend == Backend::VM && tree_only {
+            return Err("--profile, --coverage and --trace need the tree-walking backend".to_owned());
+        }
+        Ok((options, path.ok_or("expected a script to run")?))
+    }
+}
 
 struct Lox {
     has_error: bool,
+    has_runtime_error: bool,
+    options: RunOptions,
+    prompt: bool,
+    color: bool,
 }
 
 impl Lox {
-    fn new() -> Self {
-        Lox { has_error: false }
+    fn new(options: RunOptions) -> Self {
+        Lox { has_error: false, has_runtime_error: false, options, prompt: false, color: false }
     }
 
-    fn run_file(&self, path: &String) {
+    fn run_file(&mut self, path: &String) {
+        if path.ends_with(".loxc") {
+            self.run_compiled(path);
+            return;
+        }
         let input = File::open(path).unwrap();
         let buffer = BufReader::new(input);
         let lines: Vec<String> = buffer.lines().map(|x| x.unwrap()).collect();
-        println!("running file {}", path);
-        self.run(&lines.join("\n"));
+        self.run(&lines.join("\n"), path);
+        if self.has_error {
+            process::exit(65);
+        }
+        if self.has_runtime_error {
+            process::exit(70);
+        }
+    }
+
+    fn run_compiled(&mut self, path: &String) {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("could not read {}: {}", path, e);
+                process::exit(66);
+            }
+        };
+        let compiled = match loxc::deserialize(&bytes) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                eprintln!("could not load {}: {}", path, e);
+                process::exit(65);
+            }
+        };
+        // a stale file still runs, but say so when its source is next to it
+        if let Ok(source) = fs::read_to_string(Path::new(path).with_extension("lox")) {
+            if loxc::checksum(source.as_bytes()) != compiled.source_checksum {
+                eprintln!("warning: {} is out of date with its source", path);
+            }
+        }
+        if let Err(e) = self.run_vm(&compiled.chunk) {
+            eprintln!("{}", e);
+            process::exit(70);
+        }
     }
 
-    fn run_prompt(&self) {
+    fn run_vm(&self, chunk: &Chunk) -> Result<(), RuntimeErr> {
+        let mut vm = VM::new();
+        vm.set_trace_exec(self.options.trace_exec);
+        if let Some(depth) = self.options.max_call_depth {
+            vm.set_max_call_depth(depth);
+        }
+        let res = vm.run(chunk);
+        if self.options.gc_stats {
+            let stats = vm.gc_stats();
+            eprintln!(
+                "gc: {} collections, {} objects allocated, {} freed, {} live ({} bytes)",
+                stats.collections, stats.allocated, stats.freed, stats.live, stats.bytes
+            );
+        }
+        res
+    }
+
+    fn run_prompt(&mut self) {
+        self.prompt = true;
+        self.color = io::stdin().is_terminal() && stdout().is_terminal();
         print!("> ");
         _ = stdout().flush();
         let mut input: String;
@@ -31,47 +173,181 @@ impl Lox {
             if input.to_lowercase() == ".exit" {
                 break;
             }
-            self.run(&input);
+            self.run(&input, "<stdin>");
+            self.has_error = false;
+            self.has_runtime_error = false;
             print!("> ");
             _ = stdout().flush();
         }
     }
 
-    fn run(&self, input: &String){
-        let mut scanner = scanner::Scanner::new(input.to_owned());
-        let tokens_result = scanner.scan_tokens(); 
-        match &tokens_result {
-            Err(_) => {return}
-            Ok(_) => {}
-        }
-
-        let tokens = tokens_result.unwrap();
-        println!("{:?}", tokens);
-        
-        let mut parser = parser::Parser::new(tokens);
-        let expr = parser.parse();
-        if expr.is_none() {
-            eprintln!("parsing err!");
+    fn run(&mut self, input: &str, path: &str){
+        // the prompt looks at the whole line first; scripts stream straight into the parser
+        if self.prompt {
+            let mut scanner = scanner::Scanner::new(input);
+            let tokens_result = scanner.by_ref().collect::<Result<Vec<RawToken>, _>>();
+            if tokens_result.is_err() {
+                self.has_error = true;
+                return;
+            }
+
+            let tokens = tokens_result.unwrap();
+            if self.color {
+                // redraw the line the user just typed in color
+                print!("\x1b[1A\r\x1b[2K> {}\n", highlight::highlight(input, &tokens, scanner.comments(), highlight::Style::Ansi));
+            }
+
+            // a bare expression typed at the prompt has its value echoed
+            let last = tokens.iter().rev().nth(1).map(|t| t.variant);
+            if last.is_some() && !matches!(last, Some(TokenType::SEMICOLON) | Some(TokenType::RIGHT_BRACE)) {
+                self.echo(input);
+                return;
+            }
+        }
+
+        let mut parser = parser::Parser::new(scanner::Scanner::new(input));
+        let parsed = parser.parse();
+        if parsed.is_none() {
+            self.has_error = true;
+            return;
+        }
+        let parsed = parsed.unwrap();
+        if !checker::report(&parsed) {
+            self.has_error = true;
+            return;
+        }
+        let program = optimizer::optimize(&parsed);
+
+        if self.options.backend == Backend::VM {
+            let chunk = compiler::compile(&program);
+            if chunk.is_none() {
+                self.has_error = true;
+                return;
+            }
+            if let Err(e) = self.run_vm(&chunk.unwrap()) {
+                eprintln!("{}", e);
+                self.has_runtime_error = true;
+            }
             return;
         }
 
         let mut interpreter = interpreter::Interpreter::new();
-        interpreter.interpret(expr.unwrap());        
+        if let Some(depth) = self.options.max_call_depth {
+            interpreter.set_max_call_depth(depth);
+        }
+        if self.options.profile {
+            interpreter.set_profiler(Profiler::new());
+        }
+        if self.options.coverage.is_some() {
+            interpreter.set_coverage(Coverage::new());
+        }
+        if self.options.trace {
+            let out: Box<dyn Write> = match &self.options.trace_file {
+                Some(path) => match File::create(path) {
+                    Ok(f) => Box::new(io::BufWriter::new(f)),
+                    Err(e) => {
+                        eprintln!("could not write {}: {}", path, e);
+                        self.has_error = true;
+                        return;
+                    }
+                },
+                None => Box::new(io::stderr()),
+            };
+            interpreter.set_tracer(Tracer::new(input, out));
+        }
+        if let Err(e) = interpreter.run(&program) {
+            eprintln!("{}", e);
+            self.has_runtime_error = true;
+        }
+
+        if let Some(profiler) = interpreter.take_profiler() {
+            eprint!("{}", profiler.report(input));
+            if let Some(path) = &self.options.folded {
+                if let Err(e) = fs::write(path, profiler.folded()) {
+                    eprintln!("could not write {}: {}", path, e);
+                }
+            }
+        }
+
+        if let (Some(coverage), Some(out)) = (interpreter.take_coverage(), &self.options.coverage) {
+            let source_file = fs::canonicalize(path).map(|p| p.display().to_string()).unwrap_or(path.to_owned());
+            if let Err(e) = fs::write(out, coverage.lcov(&source_file)) {
+                eprintln!("could not write {}: {}", out, e);
+            }
+        }
+    }
+
+    fn echo(&mut self, input: &str) {
+        let parsed = parser::Parser::new(scanner::Scanner::new(input)).parse_expression();
+        if parsed.is_none() {
+            self.has_error = true;
+            return;
+        }
+        let (ast, expr) = parsed.unwrap();
+        match interpreter::Interpreter::new().evaluate(&ast, expr) {
+            Ok(result) if self.color => println!("{}", highlight::value(&result.val)),
+            Ok(result) => println!("{}", result.val),
+            Err(e) => {
+                eprintln!("{}", e);
+                self.has_runtime_error = true;
+            }
+        }
     }
 }
 
 use std::{
     env,
-    fs::File,
-    io::{self, stdout, BufRead, BufReader, Write},
+    fs::{self, File},
+    path::Path,
+    io::{self, stdout, BufRead, BufReader, IsTerminal, Write},
+    process,
 };
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let lox = Lox::new();
+    if args.len() > 1 {
+        match args[1].as_str() {
+            "fmt" => process::exit(tools::fmt::run(&args[2..])),
+            "lint" => process::exit(tools::lint::run(&args[2..])),
+            "lsp" => process::exit(tools::lsp::run()),
+            "debug" => process::exit(tools::debug::run(&args[2..])),
+            "disasm" => process::exit(tools::disasm::run(&args[2..])),
+            "dap" => process::exit(tools::dap::run()),
+            "test" => process::exit(tools::test::run(&args[2..])),
+            "compile" => process::exit(tools::compile::run(&args[2..])),
+            "conform" => process::exit(tools::conform::run(&args[2..])),
+            "doc" => process::exit(tools::doc::run(&args[2..])),
+            "highlight" => process::exit(highlight::run(&args[2..])),
+            "run" => match RunOptions::parse(&args[2..]) {
+                Ok((options, path)) => {
+                    Lox::new(options).run_file(&path);
+                    process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    eprintln!("Usage: lox run [--backend=tree|vm] [--profile] [--folded <file>] [--coverage <file>] [--trace] [--trace-file <file>] [--trace-exec] [--gc-stats] [--max-call-depth <n>] script");
+                    process::exit(64);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    let mut lox = Lox::new(RunOptions::default());
     if args.len() > 2 {
         println!("Usage: lox [script]");
-        return;
+        println!("       lox run [--backend=tree|vm] [--profile] [--folded <file>] [--coverage <file>] [--trace] [--trace-file <file>] [--trace-exec] [--gc-stats] [--max-call-depth <n>] script");
+        println!("       lox fmt [--check] [files]");
+        println!("       lox lint [--allow <lint>]... [--list] files");
+        println!("       lox lsp");
+        println!("       lox debug file.lox");
+        println!("       lox disasm [--trace-exec] file.lox|file.loxc");
+        println!("       lox dap");
+        println!("       lox test [--format human|tap|junit] [paths]");
+        println!("       lox compile file.lox [-o file.loxc]");
+        println!("       lox conform [--verbose] [--backend=tree|vm] <dirs or files>");
+        println!("       lox doc [--format markdown|html] [--out <dir>] files");
+        println!("       lox highlight [--format ansi|html] [file]");
     } else if args.len() == 2 {
         lox.run_file(&args[1]);
     } else {
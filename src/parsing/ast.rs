@@ -0,0 +1,73 @@
+use std::rc::Rc;
+
+use super::expressions::{Binary, Call, Expr, ExprId, Grouping, Literal, Unary, Variable};
+use super::statements::{Dec, ExprStmt, Print, Stmt, StmtId, Test};
+
+/// a pass over expressions, one method per node kind.
+/// `ast` is the arena the node and its children live in
+pub trait ExprVisitor {
+    type Output;
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) -> Self::Output;
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) -> Self::Output;
+    fn visit_literal(&mut self, ast: &Ast, l: &Literal) -> Self::Output;
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) -> Self::Output;
+    fn visit_variable(&mut self, ast: &Ast, v: &Variable) -> Self::Output;
+    fn visit_call(&mut self, ast: &Ast, c: &Call) -> Self::Output;
+}
+
+/// a pass over statements; its expressions go through an `ExprVisitor`
+pub trait StmtVisitor {
+    type Output;
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) -> Self::Output;
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) -> Self::Output;
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) -> Self::Output;
+    fn visit_test(&mut self, ast: &Ast, t: &Test) -> Self::Output;
+}
+
+/// arena owning every node of a parse; nodes refer to each other by id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ast {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Ast::default()
+    }
+
+    pub fn add_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.0 as usize]
+    }
+
+    pub fn visit_expr<V: ExprVisitor + ?Sized>(&self, id: ExprId, v: &mut V) -> V::Output {
+        self.expr(id).accept(self, v)
+    }
+
+    pub fn visit_stmt<V: StmtVisitor + ?Sized>(&self, id: StmtId, v: &mut V) -> V::Output {
+        self.stmt(id).accept(self, v)
+    }
+}
+
+/// a parsed script: its arena, shared cheaply between passes, and the top level statements in order
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub ast: Rc<Ast>,
+    pub statements: Vec<StmtId>,
+}
@@ -0,0 +1,151 @@
+use crate::errors::err;
+
+use super::ast::{Ast, ExprVisitor, Program, StmtVisitor};
+use super::expressions::{Binary, Call, Grouping, Literal, Unary, Variable};
+use super::statements::{Dec, ExprStmt, Print, Test};
+use super::symbol::Symbol;
+use super::tokens::Token;
+
+/// finds the mistakes that are certain before anything runs, as (where, message) in source order:
+/// reading a local in its own initializer and redeclaring a local in the same scope
+pub fn check(program: &Program) -> Vec<(Token, String)> {
+    let mut checker = Checker { scopes: Vec::new(), errors: Vec::new() };
+    for s in program.statements.iter() {
+        program.ast.visit_stmt(*s, &mut checker);
+    }
+    checker.errors
+}
+
+/// runs `check` and reports its errors the way the parser reports syntax errors, true when there were none
+pub fn report(program: &Program) -> bool {
+    let errors = check(program);
+    for (token, message) in errors.iter() {
+        err::error_at(token, message);
+    }
+    errors.is_empty()
+}
+
+struct Checker {
+    // names declared in each open block, innermost last, and whether their initializer has finished
+    scopes: Vec<Vec<(Symbol, bool)>>,
+    errors: Vec<(Token, String)>,
+}
+
+impl Checker {
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push((token.clone(), message.to_owned()));
+    }
+}
+
+impl ExprVisitor for Checker {
+    type Output = ();
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) {
+        ast.visit_expr(b.left, self);
+        ast.visit_expr(b.right, self);
+    }
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) {
+        ast.visit_expr(g.expr, self);
+    }
+    fn visit_literal(&mut self, _ast: &Ast, _l: &Literal) {}
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) {
+        ast.visit_expr(u.right, self);
+    }
+    fn visit_variable(&mut self, _ast: &Ast, v: &Variable) {
+        let initializing = match self.scopes.last() {
+            Some(scope) => scope.iter().any(|(name, defined)| *name == v.symbol && !defined),
+            None => false,
+        };
+        if initializing {
+            self.error(&v.name, "Can't read local variable in its own initializer.");
+        }
+    }
+    fn visit_call(&mut self, ast: &Ast, c: &Call) {
+        ast.visit_expr(c.callee, self);
+        for a in c.arguments.iter() {
+            ast.visit_expr(*a, self);
+        }
+    }
+}
+
+impl StmtVisitor for Checker {
+    type Output = ();
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) {
+        ast.visit_expr(s.expr, self);
+    }
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) {
+        ast.visit_expr(s.expr, self);
+    }
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) {
+        // globals may be redeclared, and read while initializing, like at the prompt
+        if self.scopes.is_empty() {
+            ast.visit_expr(d.expr, self);
+            return;
+        }
+        if self.scopes.last().unwrap().iter().any(|(name, _)| *name == d.symbol) {
+            self.error(&d.name, "Already a variable with this name in this scope.");
+        }
+        self.scopes.last_mut().unwrap().push((d.symbol, false));
+        ast.visit_expr(d.expr, self);
+        self.scopes.last_mut().unwrap().last_mut().unwrap().1 = true;
+    }
+    fn visit_test(&mut self, ast: &Ast, t: &Test) {
+        self.scopes.push(Vec::new());
+        for s in t.body.iter() {
+            ast.visit_stmt(*s, self);
+        }
+        self.scopes.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+
+    // syntax errors first, then what the checker finds in a program that parsed
+    fn errors(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(Scanner::new(source));
+        match parser.parse() {
+            Some(program) => check(&program).into_iter().map(|(_, message)| message).collect(),
+            None => parser.errors().iter().map(|e| e.message.clone()).collect(),
+        }
+    }
+
+    #[test]
+    fn reading_a_local_in_its_own_initializer() {
+        assert_eq!(errors("test \"t\" { var a = a; }"), vec!["Can't read local variable in its own initializer."]);
+        assert_eq!(errors("test \"t\" { var a = 1; test \"u\" { var b = a; } }"), Vec::<String>::new());
+        assert_eq!(errors("var a = 1;\nvar a = a;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn redeclaring_a_local_in_the_same_scope() {
+        assert_eq!(errors("test \"t\" { var a = 1; var a = 2; }"), vec!["Already a variable with this name in this scope."]);
+        assert_eq!(errors("test \"t\" { var a = 1; test \"u\" { var a = 2; } }"), Vec::<String>::new());
+        assert_eq!(errors("var a = 1;\nvar a = 2;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let source = "test \"t\" { var a = 1; var a = 2; var b = b; }";
+        assert_eq!(errors(source).len(), 2);
+    }
+
+    #[test]
+    fn parsing_alone_does_not_check() {
+        let mut parser = Parser::new(Scanner::new("test \"t\" { var a = 1; var a = 2; }"));
+        assert!(parser.parse().is_some());
+        assert!(parser.errors().is_empty());
+    }
+
+    // there are no statements for these keywords yet, so the parser rejects them wherever they appear
+    #[test]
+    fn keywords_without_statements_do_not_parse() {
+        for source in ["return 1;", "print this;", "print super.x;", "test \"t\" { return; }"] {
+            assert!(errors(source).contains(&"Expect expression.".to_owned()), "{}", source);
+        }
+    }
+}
@@ -1,58 +1,89 @@
-use crate::errors::err::RuntimeErr;
-use crate::runtime::interpreter::Visitor;
+use std::rc::Rc;
 
+use super::ast::{Ast, ExprVisitor};
+use super::symbol::Symbol;
 use super::tokens::Token;
 
-pub trait Expr {
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr>;
+/// index of an expression in its `Ast`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Binary(Binary),
+    Grouping(Grouping),
+    Literal(Literal),
+    Unary(Unary),
+    Variable(Variable),
+    Call(Call),
 }
 
+impl Expr {
+    pub fn accept<V: ExprVisitor + ?Sized>(&self, ast: &Ast, v: &mut V) -> V::Output {
+        match self {
+            Expr::Binary(b) => v.visit_binary(ast, b),
+            Expr::Grouping(g) => v.visit_grouping(ast, g),
+            Expr::Literal(l) => v.visit_literal(ast, l),
+            Expr::Unary(u) => v.visit_unary(ast, u),
+            Expr::Variable(var) => v.visit_variable(ast, var),
+            Expr::Call(c) => v.visit_call(ast, c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Binary {
-    pub left: Box<dyn Expr>,
+    pub left: ExprId,
     pub operator: Token,
-    pub right: Box<dyn Expr>,
+    pub right: ExprId,
 }
 
 impl Binary {
-    pub fn new(left: Box<dyn Expr>, operator: Token, right: Box<dyn Expr>) -> Self{
+    pub fn new(left: ExprId, operator: Token, right: ExprId) -> Self{
         Binary { left, operator, right}
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Grouping {
-    pub expr: Box<dyn Expr>,
+    pub expr: ExprId,
 }
 
 impl Grouping {
-    pub fn new(expr: Box<dyn Expr>) -> Self {
+    pub fn new(expr: ExprId) -> Self {
         Grouping { expr }
     }
 }
 
+/// a function implemented in Rust, such as `assert`
+#[derive(Clone, Debug)]
+pub struct NativeFn {
+    pub name: &'static str,
+    pub arity: usize,
+    pub func: fn(&[Value]) -> Result<Value, String>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Value {
-    S(String),
+    // literals share their text with the symbol table, strings built at run time own theirs.
+    // variables are looked up by `Symbol` id, string values still compare by text
+    S(Rc<str>),
     Int(f64),
     Bool(bool),
+    Native(NativeFn),
     Nil
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Literal {
     pub val: Value,
-    token: Token,
+    pub token: Token,
 }
 
 impl Literal {
     pub fn new(token: Token, val: Value) -> Self {
         Literal { val, token }
     }
-    pub fn default() -> Self {
-        return Literal::new(
-            Token::new(super::tokens::TokenType::NIL, "NIL".to_owned(), 1),
-            Value::Nil
-        )
-    }
 }
 
 impl PartialEq for Value {
@@ -61,50 +92,74 @@ impl PartialEq for Value {
             (Value::Int(a), Value::Int(b)) => {
                 a == b
             }
+            // literals share the interned text, so equal ones are the same pointer; strings built at run time
+            // have their own copy and compare by text
             (Value::S(a), Value::S(b)) => {
-                a == b
+                Rc::ptr_eq(a, b) || a == b
             }
             (Value::Bool(a), Value::Bool(b)) => {
                 a == b
             }
+            (Value::Native(a), Value::Native(b)) => {
+                a.name == b.name
+            }
             (Value::Nil, Value::Nil) => {true}
             (_, _) => {false}
         } 
     }
+}
 
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::S(s) => write!(f, "{}", s),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Native(_) => write!(f, "<native fn>"),
+            Value::Nil => write!(f, "nil"),
+        }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Unary {
     pub operator: Token,
-    pub right: Box<dyn Expr>,
+    pub right: ExprId,
 }
 
 impl Unary {
-    pub fn new(operator: Token, right: Box<dyn Expr>) -> Self {
+    pub fn new(operator: Token, right: ExprId) -> Self {
         Unary { operator, right }
     }
 }
 
-impl Expr for Binary{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        return p.visit_binary(self);
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub callee: ExprId,
+    pub paren: Token,
+    pub arguments: Vec<ExprId>,
 }
-impl Expr for Grouping{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        return p.visit_grouping(self);
+
+impl Call {
+    pub fn new(callee: ExprId, paren: Token, arguments: Vec<ExprId>) -> Self {
+        Call { callee, paren, arguments }
     }
 }
-impl Expr for Literal{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        return p.visit_literal(self);
-    }
+
+/// numbers the variable references of one parse, so a pass can record facts about each of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VarId(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: Token,
+    pub symbol: Symbol,
+    pub id: VarId,
 }
-impl Expr for Unary{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        p.visit_unary(self)
+
+impl Variable {
+    pub fn new(name: Token, id: VarId) -> Self {
+        let symbol = Symbol::intern(&name.lexeme);
+        Variable { name, symbol, id }
     }
 }
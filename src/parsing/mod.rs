@@ -1,6 +1,11 @@
+pub mod ast;
 pub mod scanner;
 pub mod parser;
 pub mod tokens;
 pub mod expressions;
 pub mod statements;
-mod keywords;
+pub mod optimizer;
+pub mod resolver;
+pub mod checker;
+pub mod keywords;
+pub mod symbol;
@@ -0,0 +1,238 @@
+use std::rc::Rc;
+
+use super::ast::{Ast, ExprVisitor, Program, StmtVisitor};
+use super::expressions::{Binary, Call, Expr, ExprId, Grouping, Literal, Unary, Value, Variable};
+use super::statements::{Dec, ExprStmt, Print, Stmt, StmtId, Test};
+use super::symbol::Symbol;
+use super::tokens::{Token, TokenType};
+
+/// folds constant subexpressions, copying the program into a fresh arena.
+/// anything that could fail at run time is left alone so the error still happens, at the same token
+pub fn optimize(program: &Program) -> Program {
+    let mut folder = Folder { ast: Ast::new() };
+    let statements = program
+        .statements
+        .iter()
+        .filter_map(|s| program.ast.visit_stmt(*s, &mut folder))
+        .collect();
+    Program { ast: Rc::new(folder.ast), statements }
+}
+
+/// an expression after folding: either a known value or a node in the new arena
+enum Folded {
+    Constant(Literal),
+    Node(ExprId),
+}
+
+struct Folder {
+    ast: Ast,
+}
+
+impl Folder {
+    fn node(&mut self, folded: Folded) -> ExprId {
+        match folded {
+            Folded::Constant(l) => self.ast.add_expr(Expr::Literal(l)),
+            Folded::Node(id) => id,
+        }
+    }
+
+    fn expr(&mut self, ast: &Ast, e: ExprId) -> ExprId {
+        let folded = ast.visit_expr(e, self);
+        self.node(folded)
+    }
+}
+
+fn is_truthy(val: &Value) -> bool {
+    match val {
+        Value::Nil => false,
+        Value::Bool(b) => *b,
+        _ => true,
+    }
+}
+
+/// the interpreter's binary semantics, or `None` where it would raise an error
+fn binary(operator: &Token, left: &Value, right: &Value) -> Option<Value> {
+    let val = match (&operator.variant, left, right) {
+        // division by zero is left for run time, however it ends up being reported
+        (TokenType::SLASH, Value::Int(_), Value::Int(y)) if *y == 0.0 => return None,
+        (TokenType::PLUS, Value::Int(x), Value::Int(y)) => Value::Int(x + y),
+        (TokenType::SLASH, Value::Int(x), Value::Int(y)) => Value::Int(x / y),
+        (TokenType::MINUS, Value::Int(x), Value::Int(y)) => Value::Int(x - y),
+        (TokenType::STAR, Value::Int(x), Value::Int(y)) => Value::Int(x * y),
+        (TokenType::PLUS, Value::S(x), Value::S(y)) => Value::S(Symbol::intern(&format!("{}{}", x, y)).as_str()),
+        (TokenType::GREATER, Value::Int(x), Value::Int(y)) => Value::Bool(x > y),
+        (TokenType::GREATER_EQUAL, Value::Int(x), Value::Int(y)) => Value::Bool(x >= y),
+        (TokenType::LESS, Value::Int(x), Value::Int(y)) => Value::Bool(x < y),
+        (TokenType::LESS_EQUAL, Value::Int(x), Value::Int(y)) => Value::Bool(x <= y),
+        (TokenType::EQUAL_EQUAL, x, y) => Value::Bool(x == y),
+        (TokenType::BANG_EQUAL, x, y) => Value::Bool(x != y),
+        _ => return None,
+    };
+    Some(val)
+}
+
+/// the interpreter's unary semantics; `-` on a non-number is nil there, not an error
+fn unary(operator: &Token, right: &Value) -> Value {
+    match (&operator.variant, right) {
+        (TokenType::MINUS, Value::Int(i)) => Value::Int(-i),
+        (TokenType::BANG, v) => Value::Bool(!is_truthy(v)),
+        _ => Value::Nil,
+    }
+}
+
+impl ExprVisitor for Folder {
+    type Output = Folded;
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) -> Folded {
+        let left = ast.visit_expr(b.left, self);
+        let right = ast.visit_expr(b.right, self);
+        if let (Folded::Constant(l), Folded::Constant(r)) = (&left, &right) {
+            if let Some(val) = binary(&b.operator, &l.val, &r.val) {
+                return Folded::Constant(Literal::new(b.operator.clone(), val));
+            }
+        }
+        let left = self.node(left);
+        let right = self.node(right);
+        Folded::Node(self.ast.add_expr(Expr::Binary(Binary::new(left, b.operator.clone(), right))))
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) -> Folded {
+        match ast.visit_expr(g.expr, self) {
+            Folded::Constant(l) => Folded::Constant(l),
+            Folded::Node(id) => Folded::Node(self.ast.add_expr(Expr::Grouping(Grouping::new(id)))),
+        }
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, l: &Literal) -> Folded {
+        Folded::Constant(l.clone())
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) -> Folded {
+        match ast.visit_expr(u.right, self) {
+            Folded::Constant(l) => Folded::Constant(Literal::new(u.operator.clone(), unary(&u.operator, &l.val))),
+            Folded::Node(id) => Folded::Node(self.ast.add_expr(Expr::Unary(Unary::new(u.operator.clone(), id)))),
+        }
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, v: &Variable) -> Folded {
+        Folded::Node(self.ast.add_expr(Expr::Variable(v.clone())))
+    }
+
+    fn visit_call(&mut self, ast: &Ast, c: &Call) -> Folded {
+        let callee = self.expr(ast, c.callee);
+        let arguments = c.arguments.iter().map(|a| self.expr(ast, *a)).collect();
+        Folded::Node(self.ast.add_expr(Expr::Call(Call::new(callee, c.paren.clone(), arguments))))
+    }
+}
+
+impl StmtVisitor for Folder {
+    /// `None` for a statement that can be dropped without changing what the program does
+    type Output = Option<StmtId>;
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) -> Option<StmtId> {
+        // a lone constant does nothing, but keep the statement so `--trace` still shows its value
+        let expr = self.expr(ast, s.expr);
+        Some(self.ast.add_stmt(Stmt::Expression(ExprStmt::new(expr, s.line))))
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) -> Option<StmtId> {
+        let expr = self.expr(ast, s.expr);
+        Some(self.ast.add_stmt(Stmt::Print(Print::new(expr, s.line))))
+    }
+
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) -> Option<StmtId> {
+        let expr = self.expr(ast, d.expr);
+        Some(self.ast.add_stmt(Stmt::Dec(Dec::new(d.name.clone(), expr))))
+    }
+
+    fn visit_test(&mut self, ast: &Ast, t: &Test) -> Option<StmtId> {
+        let body = t.body.iter().filter_map(|s| ast.visit_stmt(*s, self)).collect();
+        Some(self.ast.add_stmt(Stmt::Test(Test::new(t.name.clone(), body))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::optimize;
+    use crate::parsing::ast::Program;
+    use crate::parsing::expressions::{Expr, Value, VarId};
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::parsing::statements::Stmt;
+
+    fn optimized(source: &str) -> Program {
+        optimize(&Parser::new(Scanner::new(source)).parse().unwrap())
+    }
+
+    // the expression of the first statement, which must be a `print`
+    fn printed(program: &Program) -> &Expr {
+        match program.ast.stmt(program.statements[0]) {
+            Stmt::Print(p) => program.ast.expr(p.expr),
+            _ => panic!("not a print statement"),
+        }
+    }
+
+    fn folded(source: &str) -> Option<Value> {
+        match printed(&optimized(source)) {
+            Expr::Literal(l) => Some(l.val.clone()),
+            _ => None,
+        }
+    }
+
+    // the variables of `print a + b;`, the second statement of the first test block
+    fn operands(program: &Program) -> Vec<(String, VarId)> {
+        let body = match program.ast.stmt(program.statements[1]) {
+            Stmt::Test(t) => &t.body,
+            _ => panic!("not a test block"),
+        };
+        let print = match program.ast.stmt(body[1]) {
+            Stmt::Print(p) => p,
+            _ => panic!("not a print statement"),
+        };
+        let binary = match program.ast.expr(print.expr) {
+            Expr::Binary(b) => b,
+            _ => panic!("not a binary expression"),
+        };
+        [binary.left, binary.right]
+            .iter()
+            .map(|e| match program.ast.expr(*e) {
+                Expr::Variable(v) => (v.name.lexeme.clone(), v.id),
+                _ => panic!("not a variable"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folds_arithmetic_and_comparisons() {
+        assert_eq!(folded("print 1 + 2 * (3 - 1);"), Some(Value::Int(5.0)));
+        assert_eq!(folded("print -(4 / 2);"), Some(Value::Int(-2.0)));
+        assert_eq!(folded("print 1 < 2 == !nil;"), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn folds_string_concatenation() {
+        assert_eq!(folded("print \"a\" + \"b\" + \"c\";"), Some(Value::S("abc".into())));
+    }
+
+    #[test]
+    fn leaves_division_by_zero_for_run_time() {
+        assert_eq!(folded("print 1 / 0;"), None);
+        assert_eq!(folded("print 2 * (1 / 0);"), None);
+    }
+
+    #[test]
+    fn leaves_operations_that_fail_at_run_time() {
+        assert_eq!(folded("print 1 + \"a\";"), None);
+        assert_eq!(folded("print 1 + a;"), None);
+    }
+
+    #[test]
+    fn keeps_variable_ids() {
+        // the folded `2 + 3` and `1 + 1` shift the new arena, but references keep the ids the parser gave them
+        let source = "var a = 1 + 1;\ntest \"t\" { var b = 2 + 3; print a + b; }\nprint a;";
+        let original = Parser::new(Scanner::new(source)).parse().unwrap();
+        let kept = operands(&optimized(source));
+        assert_eq!(kept, operands(&original));
+        assert_eq!(kept, vec![("a".to_owned(), VarId(0)), ("b".to_owned(), VarId(1))]);
+    }
+}
@@ -1,16 +1,45 @@
-use crate::errors::err::error;
+// the recursive descent below predates the clippy gate and keeps its is_none/unwrap style
+#![allow(clippy::needless_return, clippy::question_mark, clippy::unnecessary_unwrap)]
 
+use std::mem;
+use std::rc::Rc;
+
+use crate::errors::err::{error_at, SyntaxErr};
+
+use super::ast::{Ast, Program};
 use super::statements::*;
-use super::tokens::{Token, TokenType};
+use super::symbol::Symbol;
+use super::scanner::Scanner;
+use super::tokens::{RawToken, Token, TokenType};
 use super::expressions::*;
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+/// how deep groupings, unary operands, call arguments and blocks may nest, and how tall an expression may grow.
+/// binary operators and calls chain in loops, so the parser never recurses on `1 + 1 + ...` or `f()()...`,
+/// but every pass after it walks the tree recursively, so each link in a chain counts as a level
+const MAX_NESTING: u32 = 256;
+
+/// pulls tokens from the scanner as it goes, looking at most two ahead
+pub struct Parser<'src> {
+    scanner: Scanner<'src>,
+    previous: RawToken<'src>,
+    current: RawToken<'src>,
+    // the token after `current`, once something has looked at it
+    next: Option<RawToken<'src>>,
+    // once the scanner fails, the rest of the parse is not worth reporting
+    scan_failed: bool,
+    has_error: bool,
+    errors: Vec<SyntaxErr>,
+    ast: Ast,
+    // variable references so far, see `VarId`
+    variables: u32,
+    // current recursion depth, see `nested`
+    depth: u32,
+    // height of every expression added so far, indexed by `ExprId`
+    heights: Vec<u32>,
 }
 
-impl Parser {
-    pub fn parse(&mut self) -> Option<Vec<Box<dyn Stmt>>> {
+impl<'src> Parser<'src> {
+    pub fn parse(&mut self) -> Option<Program> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
             let cur = self.declaration();
@@ -19,15 +48,63 @@ impl Parser {
             }
         }
 
-        return Some(statements)
+        if self.has_error {
+            return None;
+        }
+        Some(Program { ast: Rc::new(mem::take(&mut self.ast)), statements })
+    }
+
+    pub fn new(scanner: Scanner<'src>) -> Self {
+        let eof = RawToken { variant: TokenType::EOF, lexeme: "", line: 1, column: 1 };
+        let mut parser = Parser {
+            scanner,
+            previous: eof,
+            current: eof,
+            next: None,
+            scan_failed: false,
+            has_error: false,
+            errors: Vec::new(),
+            ast: Ast::new(),
+            variables: 0,
+            depth: 0,
+            heights: Vec::new(),
+        };
+        parser.current = parser.pull();
+        parser
     }
 
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+    /// parses source holding a single expression, such as one typed at the debugger prompt
+    pub fn parse_expression(&mut self) -> Option<(Ast, ExprId)> {
+        let expr = self.expression();
+        if expr.is_some() && !self.is_at_end() {
+            self.error("Expect end of expression.");
+            return None;
+        }
+        expr.map(|e| (mem::take(&mut self.ast), e))
     }
 
-    fn declaration(&mut self) -> Option<Box<dyn Stmt>> {
-        if self.matching(&vec![TokenType::IDENTIFIER]){
+    pub fn errors(&self) -> &[SyntaxErr] {
+        &self.errors
+    }
+
+    /// the scanner, for what it gathered on the side, such as comments
+    pub fn scanner(&self) -> &Scanner<'src> {
+        &self.scanner
+    }
+
+    fn declaration(&mut self) -> Option<StmtId> {
+        // `test` is only a keyword when a test name follows it
+        if self.check(TokenType::IDENTIFIER) && self.peek().lexeme == "test" && self.check_next(TokenType::STRING) {
+            self.advance();
+            let res = self.test_declaration();
+            if res.is_none() {
+                self.synchronize();
+                return None;
+            }
+            return res;
+        }
+
+        if self.matching(&[TokenType::VAR]){
             let res = self.var_declaration();
             if res.is_none() {
                 self.synchronize();
@@ -44,14 +121,14 @@ impl Parser {
         res
     }
 
-    fn var_declaration(&mut self) -> Option<Box<dyn Stmt>> {
+    fn var_declaration(&mut self) -> Option<StmtId> {
         let name = self.consume(TokenType::IDENTIFIER, "expect var name".to_owned());
         if name.is_none() {
             return None;
         }
 
-        let mut init: Option<Box<dyn Expr>> = None;
-        if self.matching(&vec![TokenType::EQUAL]) {
+        let mut init: Option<ExprId> = None;
+        if self.matching(&[TokenType::EQUAL]) {
             init = self.expression(); 
         }
 
@@ -60,41 +137,110 @@ impl Parser {
         }
 
         self.consume(TokenType::SEMICOLON, "expecting ';' after variable declaration".to_owned());
-        return Some(Box::new(Dec::new(name.unwrap(), init.unwrap())));
+        return Some(self.ast.add_stmt(Stmt::Dec(Dec::new(name.unwrap().to_token(), init.unwrap()))));
     }
 
-    fn statement(&mut self) -> Option<Box<dyn Stmt>> {
-        if self.matching(&vec![TokenType::PRINT]) {
+    fn test_declaration(&mut self) -> Option<StmtId> {
+        let name = self.consume(TokenType::STRING, "Expect test name.".to_owned())?;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before test body.".to_owned())?;
+
+        // a block past the limit is skipped whole, so its statements and braces add no more errors
+        let body = match self.nested("Blocks too deeply nested.", |p| Some(p.block())) {
+            Some(body) => body,
+            None => {
+                self.skip_block();
+                return Some(self.ast.add_stmt(Stmt::Test(Test::new(name.to_token(), Vec::new()))));
+            }
+        };
+
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after test body.".to_owned())?;
+        Some(self.ast.add_stmt(Stmt::Test(Test::new(name.to_token(), body))))
+    }
+
+    fn block(&mut self) -> Vec<StmtId> {
+        let mut body = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                body.push(stmt);
+            }
+        }
+        body
+    }
+
+    /// skips past the '}' closing the block whose '{' was just consumed
+    fn skip_block(&mut self) {
+        let mut open = 1;
+        while open > 0 && !self.is_at_end() {
+            match self.advance().variant {
+                TokenType::LEFT_BRACE => open += 1,
+                TokenType::RIGHT_BRACE => open -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Option<StmtId> {
+        if self.matching(&[TokenType::PRINT]) {
             return self.print_statement();
         }
         self.expression_stmt()
     }
 
-    fn expression(&mut self) -> Option<Box<dyn Expr>> {
-        return self.equality();
+    fn expression(&mut self) -> Option<ExprId> {
+        self.nested("Expression too deeply nested.", |p| p.equality())
+    }
+
+    /// runs `f` one level deeper, reporting `message` rather than recursing past `MAX_NESTING`
+    fn nested<T>(&mut self, message: &str, f: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.depth >= MAX_NESTING {
+            self.error(message);
+            return None;
+        }
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
     }
 
-    fn equality(&mut self) -> Option<Box<dyn Expr>> {
+    /// adds `expr` to the tree, or reports it once the tree would be taller than `MAX_NESTING`
+    fn add_expr(&mut self, expr: Expr) -> Option<ExprId> {
+        let height = |id: &ExprId| self.heights[id.0 as usize];
+        let tallest = match &expr {
+            Expr::Binary(b) => height(&b.left).max(height(&b.right)),
+            Expr::Grouping(g) => height(&g.expr),
+            Expr::Unary(u) => height(&u.right),
+            Expr::Call(c) => c.arguments.iter().map(height).fold(height(&c.callee), u32::max),
+            Expr::Literal(_) | Expr::Variable(_) => 0,
+        };
+        if tallest >= MAX_NESTING {
+            self.error("Expression too deeply nested.");
+            return None;
+        }
+        self.heights.push(tallest + 1);
+        Some(self.ast.add_expr(expr))
+    }
+
+    fn equality(&mut self) -> Option<ExprId> {
         let val = self.comparison();
         if val.is_none() {return None;}
 
         let mut expr = val.unwrap();
 
-        while self.matching(&vec![TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
-            let operator = self.previous();
+        while self.matching(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
+            let operator = self.previous().to_token();
             let tmp_right = self.comparison();
             if tmp_right.is_none(){ return None;}
 
             let right = tmp_right.unwrap();
-            expr = Box::new(Binary::new(expr, operator, right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, operator, right)))?;
         }
 
         Some(expr)
     }
 
-    fn matching(&mut self, types: &Vec<TokenType>) -> bool {
+    fn matching(&mut self, types: &[TokenType]) -> bool {
         for t in types.iter(){
-            if self.check(t.clone()) {
+            if self.check(*t) {
                 self.advance();
                 return true;
             }
@@ -103,12 +249,12 @@ impl Parser {
         false
     }
 
-    fn comparison(&mut self) -> Option<Box<dyn Expr>> {
+    fn comparison(&mut self) -> Option<ExprId> {
         let val = self.term();
         if val.is_none() {return None;}
 
         let mut expr = val.unwrap();
-        let token_types = vec![
+        let token_types = [
             TokenType::GREATER,
             TokenType::GREATER_EQUAL,
             TokenType::LESS,
@@ -116,12 +262,12 @@ impl Parser {
         ];
 
         while self.matching(&token_types) {
-            let operator = self.previous();
+            let operator = self.previous().to_token();
             let tmp_right = self.term();
             if tmp_right.is_none(){ return None;}
 
             let right = tmp_right.unwrap();
-            expr = Box::new(Binary::new(expr, operator, right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, operator, right)))?;
         }
 
         Some(expr)
@@ -134,9 +280,38 @@ impl Parser {
         self.peek().variant == t
     }
 
-    fn advance(&mut self) -> Token {
+    fn check_next(&mut self, t: TokenType) -> bool {
+        if self.is_at_end() {
+            return false;
+        }
+        if self.next.is_none() {
+            self.next = Some(self.pull());
+        }
+        self.next.unwrap().variant == t
+    }
+
+    /// the scanner's next token; a scan error is recorded and ends the input
+    fn pull(&mut self) -> RawToken<'src> {
+        match self.scanner.next() {
+            Some(Ok(token)) => token,
+            Some(Err(e)) => {
+                let eof = RawToken { variant: TokenType::EOF, lexeme: "", line: e.line, column: e.column };
+                self.errors.push(e);
+                self.has_error = true;
+                self.scan_failed = true;
+                eof
+            }
+            None => RawToken { variant: TokenType::EOF, lexeme: "", line: self.current.line, column: self.current.column },
+        }
+    }
+
+    fn advance(&mut self) -> RawToken<'src> {
         if !self.is_at_end() {
-            self.current += 1;
+            self.previous = self.current;
+            self.current = match self.next.take() {
+                Some(token) => token,
+                None => self.pull(),
+            };
         }
         self.previous()
     }
@@ -145,158 +320,194 @@ impl Parser {
         self.peek().variant == TokenType::EOF
     }
 
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    fn peek(&self) -> RawToken<'src> {
+        self.current
     }
 
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    fn previous(&self) -> RawToken<'src> {
+        self.previous
     }
 
-    fn term(&mut self) -> Option<Box<dyn Expr>> {
+    fn term(&mut self) -> Option<ExprId> {
         let val = self.factor();
         if val.is_none() {return None;}
 
         let mut expr = val.unwrap();
-        let token_types = vec![
+        let token_types = [
             TokenType::MINUS,
             TokenType::PLUS
         ];
 
         while self.matching(&token_types) {
-            let operator = self.previous();
+            let operator = self.previous().to_token();
             let tmp_right = self.factor();
             if tmp_right.is_none() {return None;}
 
             let right = tmp_right.unwrap();
-            expr = Box::new(Binary::new(expr, operator, right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, operator, right)))?;
         }
 
         Some(expr)
     }
 
-    fn factor(&mut self) -> Option<Box<dyn Expr>> {
+    fn factor(&mut self) -> Option<ExprId> {
         let val = self.unary(); 
         if val.is_none(){ return None;}
 
         let mut expr = val.unwrap();
-        let token_types = vec![
+        let token_types = [
             TokenType::SLASH,
             TokenType::STAR
         ];
 
         while self.matching(&token_types) {
-            let operator = self.previous();
+            let operator = self.previous().to_token();
             let tmp_right = self.unary();
             if tmp_right.is_none(){return None;}
 
             let right = tmp_right.unwrap();
-            expr = Box::new(Binary::new(expr, operator, right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, operator, right)))?;
         }
 
         Some(expr)
     }
 
-    fn unary(&mut self) -> Option<Box<dyn Expr>> {
-        let token_types = vec![
+    fn unary(&mut self) -> Option<ExprId> {
+        let token_types = [
             TokenType::BANG,
             TokenType::MINUS
         ];
         if self.matching(&token_types) {
-            let operator = self.previous();
-            if let Some(right) = self.unary(){
-                return Some(Box::new(Unary::new(operator, right)));
+            let operator = self.previous().to_token();
+            let right = self.nested("Expression too deeply nested.", |p| p.unary())?;
+            return self.add_expr(Expr::Unary(Unary::new(operator, right)));
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Option<ExprId> {
+        let mut expr = self.primary()?;
+        while self.matching(&[TokenType::LEFT_PAREN]) {
+            let mut arguments = Vec::new();
+            if !self.check(TokenType::RIGHT_PAREN) {
+                loop {
+                    if arguments.len() >= 255 {
+                        self.error("Can't have more than 255 arguments.");
+                    }
+                    arguments.push(self.expression()?);
+                    if !self.matching(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
             }
+            let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.".to_owned())?;
+            expr = self.add_expr(Expr::Call(Call::new(expr, paren.to_token(), arguments)))?;
         }
 
-        self.primary()
+        Some(expr)
     }
 
-    fn primary(&mut self) -> Option<Box<dyn Expr>> {
-        if self.matching(&vec![TokenType::FALSE]) {
-            return Some(Box::new(
+    fn primary(&mut self) -> Option<ExprId> {
+        if self.matching(&[TokenType::FALSE]) {
+            return self.add_expr(Expr::Literal(
                     Literal::new(
-                        self.previous(),
+                        self.previous().to_token(),
                         Value::Bool(false)
                     )
                 ));
         }
-        if self.matching(&vec![TokenType::TRUE]) {
-            return Some(Box::new(
+        if self.matching(&[TokenType::TRUE]) {
+            return self.add_expr(Expr::Literal(
                     Literal::new(
-                        self.previous(),
+                        self.previous().to_token(),
                         Value::Bool(true)
                         )
                     ));
         }
-        if self.matching(&vec![TokenType::NIL]) {
-            return Some(Box::new(
+        if self.matching(&[TokenType::NIL]) {
+            return self.add_expr(Expr::Literal(
                     Literal::new(
-                        self.previous(),
+                        self.previous().to_token(),
                         Value::Nil
                         )
                     ));
         }
-        if self.matching(&vec![TokenType::NUMBER]) {
-            let val = self.previous().int.unwrap();
-            return Some(Box::new(
+        if self.matching(&[TokenType::NUMBER]) {
+            let token = self.previous().to_token();
+            let val = token.int.unwrap();
+            return self.add_expr(Expr::Literal(
                     Literal::new(
-                        self.previous(),
+                        token,
                         Value::Int(val)
                         )
                     ));
         }
-        if self.matching(&vec![TokenType::STRING]) {
-            let s = self.previous().string.unwrap();
-            return Some(Box::new(
+        if self.matching(&[TokenType::STRING]) {
+            let token = self.previous().to_token();
+            let s = token.string.clone().unwrap();
+            return self.add_expr(Expr::Literal(
                     Literal::new(
-                        self.previous(),
-                        Value::S(s)
+                        token,
+                        Value::S(Symbol::intern(&s).as_str())
                         )
                     ));
         }
-        if self.matching(&vec![TokenType::LEFT_PAREN]) {
+        if self.matching(&[TokenType::IDENTIFIER]) {
+            let id = VarId(self.variables);
+            self.variables += 1;
+            return self.add_expr(Expr::Variable(Variable::new(self.previous().to_token(), id)));
+        }
+        if self.matching(&[TokenType::LEFT_PAREN]) {
            let val = self.expression(); 
            if val.is_none(){ return None; }
 
            let expr = val.unwrap();
            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.".to_owned());
-           return Some(Box::new(Grouping::new(expr)));
+           return self.add_expr(Expr::Grouping(Grouping::new(expr)));
         }
 
+        self.error("Expect expression.");
         None
     }
 
-    fn print_statement(&mut self) -> Option<Box<dyn Stmt>> {
-        let expr = self.expression();
+    fn print_statement(&mut self) -> Option<StmtId> {
+        let line = self.previous().line;
+        // a broken expression has already been reported, and a missing ';' would only repeat it
+        let expr = self.expression()?;
         self.consume(TokenType::SEMICOLON, "expecting ';' after expr.".to_owned());
-    
-        if expr.is_some() {
-            return Some(Box::new(Print::new(expr.unwrap())));
-        }
-
-        None
+        Some(self.ast.add_stmt(Stmt::Print(Print::new(expr, line))))
     }
 
-    fn expression_stmt(&mut self) -> Option<Box<dyn Stmt>> {
-        let expr = self.expression();
+    fn expression_stmt(&mut self) -> Option<StmtId> {
+        let line = self.peek().line;
+        let expr = self.expression()?;
         self.consume(TokenType::SEMICOLON, "expecting ';' after expr".to_owned());
-
-        if expr.is_some() {
-            return Some(Box::new(ExprStmt::new(expr.unwrap())));
-        }
-
-        None
+        Some(self.ast.add_stmt(Stmt::Expression(ExprStmt::new(expr, line))))
     }
 
-    fn consume(&mut self, variant: TokenType, msg: String) -> Option<Token> {
+    fn consume(&mut self, variant: TokenType, msg: String) -> Option<RawToken<'src>> {
         if self.check(variant) {
             return Some(self.advance());
         } 
-        error(self.peek().line, &msg);
+        self.error(&msg);
         None
     }
 
+    fn error(&mut self, message: &str) {
+        let token = self.peek().to_token();
+        self.error_at(&token, message);
+    }
+
+    fn error_at(&mut self, token: &Token, message: &str) {
+        if self.scan_failed {
+            return;
+        }
+        error_at(token, &message.to_owned());
+        self.errors.push(SyntaxErr { message: message.to_owned(), line: token.line, column: token.column });
+        self.has_error = true;
+    }
+
     fn synchronize(&mut self){
         self.advance();
         while !self.is_at_end() {
@@ -320,3 +531,59 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Parser, MAX_NESTING};
+    use crate::parsing::scanner::Scanner;
+
+    fn errors(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(Scanner::new(source));
+        parser.parse();
+        parser.errors().iter().map(|e| e.message.clone()).collect()
+    }
+
+    fn parens(depth: u32) -> String {
+        format!("print {}1{};", "(".repeat(depth as usize), ")".repeat(depth as usize))
+    }
+
+    #[test]
+    fn deep_groupings_are_rejected_once() {
+        assert_eq!(errors(&parens(MAX_NESTING)), vec!["Expression too deeply nested."]);
+        assert!(errors(&parens(MAX_NESTING - 2)).is_empty());
+    }
+
+    #[test]
+    fn deep_unary_operators_are_rejected() {
+        let source = format!("print {}1;", "-".repeat(MAX_NESTING as usize));
+        assert_eq!(errors(&source), vec!["Expression too deeply nested."]);
+    }
+
+    #[test]
+    fn deep_blocks_are_rejected() {
+        let n = MAX_NESTING as usize;
+        // the statement after the blocks still parses
+        let source = format!("{}{}print 1;", "test \"t\" { ".repeat(n + 1), "}".repeat(n + 1));
+        assert_eq!(errors(&source), vec!["Blocks too deeply nested."]);
+        let source = format!("{}{}print 1;", "test \"t\" { ".repeat(n), "}".repeat(n));
+        assert!(errors(&source).is_empty());
+    }
+
+    #[test]
+    fn long_chains_count_as_nesting() {
+        let n = MAX_NESTING as usize;
+        let terms = |n: usize| format!("print {};", vec!["1"; n].join(" + "));
+        assert!(errors(&terms(n)).is_empty());
+        assert_eq!(errors(&terms(n + 1)), vec!["Expression too deeply nested."]);
+        // the statement after a rejected chain still parses
+        assert_eq!(errors(&format!("{}\nprint 1 +;", terms(10000))), vec!["Expression too deeply nested.", "Expect expression."]);
+        assert_eq!(errors(&format!("print {};", vec!["1 < 2"; n].join(" == "))), vec!["Expression too deeply nested."]);
+        assert_eq!(errors(&format!("gc{};", "()".repeat(n))), vec!["Expression too deeply nested."]);
+    }
+
+    #[test]
+    fn a_broken_expression_is_reported_once() {
+        assert_eq!(errors("print ;\nprint 1;"), vec!["Expect expression."]);
+        assert_eq!(errors("1 + ;"), vec!["Expect expression."]);
+    }
+}
@@ -0,0 +1,163 @@
+use super::ast::{Ast, ExprVisitor, Program, StmtVisitor};
+use super::expressions::{Binary, Call, Grouping, Literal, Unary, Variable};
+use super::statements::{Dec, ExprStmt, Print, Test};
+use super::symbol::Symbol;
+
+/// where a local variable lives at run time: `depth` block scopes out from the innermost one, at index `slot`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Local {
+    pub depth: usize,
+    pub slot: usize,
+}
+
+/// the `Local` of every variable reference that isn't a global, indexed by `VarId`
+#[derive(Debug, Default)]
+pub struct Resolution {
+    locals: Vec<Option<Local>>,
+}
+
+impl Resolution {
+    /// `None` means `v` refers to a global, looked up by name
+    pub fn local(&self, v: &Variable) -> Option<Local> {
+        self.locals.get(v.id.0 as usize).copied().flatten()
+    }
+
+    fn set(&mut self, v: &Variable, local: Local) {
+        let i = v.id.0 as usize;
+        if self.locals.len() <= i {
+            self.locals.resize(i + 1, None);
+        }
+        self.locals[i] = Some(local);
+    }
+}
+
+/// works out statically which block declares each variable reference.
+/// slots are handed out in declaration order, matching the order the interpreter defines locals in
+pub fn resolve(program: &Program) -> Resolution {
+    let mut resolver = Resolver { scopes: Vec::new(), resolution: Resolution::default() };
+    for s in program.statements.iter() {
+        program.ast.visit_stmt(*s, &mut resolver);
+    }
+    resolver.resolution
+}
+
+struct Resolver {
+    // names declared in each open block, innermost last; a name's position is its slot
+    scopes: Vec<Vec<Symbol>>,
+    resolution: Resolution,
+}
+
+impl Resolver {
+    fn declare(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(name);
+        }
+    }
+
+    fn resolve_local(&mut self, v: &Variable) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            // the checker has already rejected redeclaring a local, so names are unique within a scope
+            if let Some(slot) = scope.iter().position(|name| *name == v.symbol) {
+                self.resolution.set(v, Local { depth, slot });
+                return;
+            }
+        }
+    }
+}
+
+impl ExprVisitor for Resolver {
+    type Output = ();
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) {
+        ast.visit_expr(b.left, self);
+        ast.visit_expr(b.right, self);
+    }
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) {
+        ast.visit_expr(g.expr, self);
+    }
+    fn visit_literal(&mut self, _ast: &Ast, _l: &Literal) {}
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) {
+        ast.visit_expr(u.right, self);
+    }
+    fn visit_variable(&mut self, _ast: &Ast, v: &Variable) {
+        self.resolve_local(v);
+    }
+    fn visit_call(&mut self, ast: &Ast, c: &Call) {
+        ast.visit_expr(c.callee, self);
+        for a in c.arguments.iter() {
+            ast.visit_expr(*a, self);
+        }
+    }
+}
+
+impl StmtVisitor for Resolver {
+    type Output = ();
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) {
+        ast.visit_expr(s.expr, self);
+    }
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) {
+        ast.visit_expr(s.expr, self);
+    }
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) {
+        // the initializer runs before the name exists, so it sees any outer declaration
+        ast.visit_expr(d.expr, self);
+        self.declare(d.symbol);
+    }
+    fn visit_test(&mut self, ast: &Ast, t: &Test) {
+        self.scopes.push(Vec::new());
+        for s in t.body.iter() {
+            ast.visit_stmt(*s, self);
+        }
+        self.scopes.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, Local};
+    use crate::parsing::expressions::{VarId, Variable};
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::parsing::tokens::{Token, TokenType};
+
+    // where each variable reference resolved to, in the order the parser numbered them.
+    // the sources only refer to variables in `print` statements, one per statement
+    fn locals(source: &str) -> Vec<Option<(usize, usize)>> {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        let resolution = resolve(&program);
+        let count = source.matches("print").count();
+        (0..count as u32)
+            .map(|i| {
+                let v = Variable::new(Token::new(TokenType::IDENTIFIER, "".to_owned(), 1), VarId(i));
+                resolution.local(&v).map(|Local { depth, slot }| (depth, slot))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn globals_are_not_locals() {
+        assert_eq!(locals("var a = 1;\nprint a;\ntest \"t\" { print a; }"), vec![None, None]);
+    }
+
+    #[test]
+    fn slots_follow_declaration_order() {
+        let source = "test \"t\" { var a = 1; var b = 2; var c = 3; print c; print a; print b; }";
+        assert_eq!(locals(source), vec![Some((0, 2)), Some((0, 0)), Some((0, 1))]);
+    }
+
+    #[test]
+    fn the_same_name_resolves_per_scope() {
+        let source = "var a = 1;\n\
+            test \"one\" { var b = 2; var a = 3; print a; }\n\
+            test \"two\" { var a = 4; print a; test \"inner\" { var a = 5; print a; } print a; }\n\
+            print a;";
+        assert_eq!(locals(source), vec![Some((0, 1)), Some((0, 0)), Some((0, 0)), Some((0, 0)), None]);
+    }
+
+    #[test]
+    fn outer_locals_are_found_by_depth() {
+        let source = "test \"outer\" { var a = 1; var b = 2; test \"inner\" { var c = 3; print b; print c; } }";
+        assert_eq!(locals(source), vec![Some((1, 1)), Some((0, 0))]);
+    }
+}
@@ -1,51 +1,79 @@
+// the character helpers predate the clippy gate
+#![allow(clippy::needless_return, clippy::manual_range_contains)]
+
 use std::collections::HashMap;
 
-use super::tokens::{Token, TokenType};
+use super::tokens::{Comment, RawToken, TokenType};
 use super::keywords::keywords_map;
-use crate::errors::err;
+use crate::errors::err::{self, SyntaxErr};
 
-pub struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+/// yields the tokens of `source` one at a time, ending with `EOF`.
+/// the first error is reported and ends the stream
+pub struct Scanner<'src> {
+    source: &'src str,
     start: u32,
     current: u32,
     line: u32,
-    has_error: bool,
-    keywords: HashMap<String, TokenType>
+    line_start: u32,
+    // where the token being scanned starts; a string may end lines below
+    start_line: u32,
+    start_column: u32,
+    done: bool,
+    keywords: HashMap<String, TokenType>,
+    comments: Vec<Comment>,
+}
+
+impl<'src> Iterator for Scanner<'src> {
+    type Item = Result<RawToken<'src>, SyntaxErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        while !self.is_at_end() {
+            self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.current - self.line_start + 1;
+            if let Some(res) = self.scan_token() {
+                self.done = res.is_err();
+                return Some(res);
+            }
+        }
+        self.done = true;
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.current - self.line_start + 1;
+        self.add_token(TokenType::EOF)
+    }
 }
 
-impl Scanner {
-    pub fn new(source: String) -> Self {
+impl<'src> Scanner<'src> {
+    pub fn new(source: &'src str) -> Self {
         Scanner {
             source,
-            tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
-            has_error: false,
-            keywords: keywords_map()
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            done: false,
+            keywords: keywords_map(),
+            comments: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, ()> {
-        self.has_error = false;
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
-            if self.has_error {
-                return Err(());
-            }
-        }
-        self.tokens
-            .push(Token::new(TokenType::EOF, "".to_owned(), self.line));
-        return Ok(self.tokens.clone());
+    /// line comments seen while scanning, in source order
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len() as u32
     }
 
-    fn scan_token(&mut self) {
+    /// the next token, error, or `None` for whitespace and comments
+    fn scan_token(&mut self) -> Option<Result<RawToken<'src>, SyntaxErr>> {
         let c = self.source.as_bytes()[self.current as usize] as char;
         self.current += 1;
         match c {
@@ -67,7 +95,7 @@ impl Scanner {
                         TokenType::BANG
                     }
                 };
-                self.add_token(token);
+                self.add_token(token)
             }
             '=' => {
                 let token = {
@@ -77,7 +105,7 @@ impl Scanner {
                         TokenType::EQUAL
                     }
                 };
-                self.add_token(token);
+                self.add_token(token)
             }
             '<' => {
                 let token = {
@@ -87,7 +115,7 @@ impl Scanner {
                         TokenType::LESS
                     }
                 };
-                self.add_token(token);
+                self.add_token(token)
             }
             '>' => {
                 let token = {
@@ -97,62 +125,61 @@ impl Scanner {
                         TokenType::GREATER
                     }
                 };
-                self.add_token(token);
+                self.add_token(token)
             }
             '/' => {
                 if self.match_expected_char('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    let text = self.source[self.start as usize..self.current as usize].trim_end().to_owned();
+                    self.comments.push(Comment { text, line: self.line, column: self.start_column });
+                    None
                 } else {
                     self.add_token(TokenType::SLASH)
                 }
             }
-            ' ' => {}
-            '\r' => {}
-            '\t' => {}
+            ' ' => None,
+            '\r' => None,
+            '\t' => None,
             '\n' => {
-                self.line += 1;
+                self.new_line();
+                None
             }
             '"' => {
-                self.string();
+                self.string()
             }
             _ => {
                 if Scanner::is_digit(c){
-                    self.number();
+                    self.number()
                 }
                 else if Scanner::is_alpha(c){
-                    self.identifier();
+                    self.identifier()
                 }
                 else {
-                    err::error(self.line, &"Unexpected character.".to_owned());
-                    self.has_error = true;
+                    self.error("Unexpected character.")
                 }
             }
         }
     }
 
-    fn string(&mut self) {
+    fn string(&mut self) -> Option<Result<RawToken<'src>, SyntaxErr>> {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
             self.advance();
+            if self.previous() == '\n' {
+                self.new_line();
+            }
         }
 
         if self.is_at_end() {
-            err::error(self.line, &"Unterminated string.".to_owned());
-            self.has_error = true;
-            return;
+            return self.error("Unterminated string.");
         }
 
         self.advance();
-
-        let value = self.source[(self.start + 1) as usize..(self.current - 1) as usize].to_owned();
-        self.add_string_token(TokenType::STRING, value);
+        self.add_token(TokenType::STRING)
     }
 
-    fn number(&mut self) {
+    fn number(&mut self) -> Option<Result<RawToken<'src>, SyntaxErr>> {
         while Scanner::is_digit(self.peek()) {self.advance();}
 
         if self.peek() == '.' && Scanner::is_digit(self.peek_next()){
@@ -163,24 +190,18 @@ impl Scanner {
             }
         }
 
-        let string_value = self.source[self.start as usize .. self.current as usize].to_owned();
-        let value: f64 = string_value.parse().unwrap();
-
-        self.add_int_token(TokenType::NUMBER, value)
+        self.add_token(TokenType::NUMBER)
     }
 
-    fn identifier(&mut self) {
+    fn identifier(&mut self) -> Option<Result<RawToken<'src>, SyntaxErr>> {
         while Scanner::is_alpha_numeric(self.peek()){
             self.advance();
         } 
 
-        let text = self.source[self.start as usize .. self.current as usize].to_owned();
-        if self.keywords.contains_key(&text){
-            let token_t = self.keywords.get(&text).unwrap();
-            self.add_token(token_t.clone());
-        }
-        else {
-            self.add_token(TokenType::IDENTIFIER);
+        let text = &self.source[self.start as usize .. self.current as usize];
+        match self.keywords.get(text) {
+            Some(keyword) => self.add_token(*keyword),
+            None => self.add_token(TokenType::IDENTIFIER),
         }
     }
 
@@ -198,27 +219,29 @@ impl Scanner {
         self.source.as_bytes()[(self.current + 1) as usize] as char
     }
 
+    fn previous(&self) -> char {
+        self.source.as_bytes()[(self.current - 1) as usize] as char
+    }
+
     fn advance(&mut self) -> char {
         let res = self.source.as_bytes()[self.current as usize] as char;
         self.current += 1;
         res
     }
 
-    fn add_token(&mut self, token: TokenType) {
-        let text = self.source[self.start as usize..self.current as usize].to_owned();
-        self.tokens.push(Token::new(token, text, self.line));
+    fn add_token(&self, variant: TokenType) -> Option<Result<RawToken<'src>, SyntaxErr>> {
+        let lexeme = &self.source[self.start as usize..self.current as usize];
+        Some(Ok(RawToken { variant, lexeme, line: self.start_line, column: self.start_column }))
     }
 
-    fn add_string_token(&mut self, token: TokenType, string: String) {
-        let text = self.source[self.start as usize..self.current as usize].to_owned();
-        self.tokens
-            .push(Token::new_str(token, text, self.line, string));
+    fn error(&self, message: &str) -> Option<Result<RawToken<'src>, SyntaxErr>> {
+        err::error(self.start_line, &message.to_owned());
+        Some(Err(SyntaxErr { message: message.to_owned(), line: self.start_line, column: self.start_column }))
     }
 
-    fn add_int_token(&mut self, token: TokenType, int: f64) {
-        let text = self.source[self.start as usize..self.current as usize].to_owned();
-        self.tokens
-            .push(Token::new_int(token, text, self.line, int));
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
     }
 
     fn match_expected_char(&mut self, expected: char) -> bool {
@@ -248,3 +271,99 @@ impl Scanner {
         Scanner::is_digit(c) || Scanner::is_alpha(c)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Scanner;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::tokens::TokenType;
+
+    fn variants(source: &str) -> Vec<TokenType> {
+        Scanner::new(source).map(|t| t.unwrap().variant).collect()
+    }
+
+    #[test]
+    fn streams_tokens_ending_with_eof() {
+        assert_eq!(
+            variants("var a = 1 <= 2; // note"),
+            vec![
+                TokenType::VAR,
+                TokenType::IDENTIFIER,
+                TokenType::EQUAL,
+                TokenType::NUMBER,
+                TokenType::LESS_EQUAL,
+                TokenType::NUMBER,
+                TokenType::SEMICOLON,
+                TokenType::EOF,
+            ]
+        );
+        assert_eq!(variants(""), vec![TokenType::EOF]);
+    }
+
+    #[test]
+    fn lexemes_borrow_the_source() {
+        let source = String::from("print \"text\" + name;");
+        let range = source.as_bytes().as_ptr_range();
+        for token in Scanner::new(&source).map(Result::unwrap) {
+            let lexeme = token.lexeme.as_bytes().as_ptr_range();
+            assert!(range.start <= lexeme.start && lexeme.end <= range.end);
+        }
+        let lexemes: Vec<&str> = Scanner::new(&source).map(|t| t.unwrap().lexeme).collect();
+        assert_eq!(lexemes, vec!["print", "\"text\"", "+", "name", ";", ""]);
+    }
+
+    #[test]
+    fn tracks_lines_and_columns() {
+        let source = "var a = 1;\n  print a;\n\"two\nlines\" x";
+        let positions: Vec<(&str, u32, u32)> =
+            Scanner::new(source).map(Result::unwrap).map(|t| (t.lexeme, t.line, t.column)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("var", 1, 1),
+                ("a", 1, 5),
+                ("=", 1, 7),
+                ("1", 1, 9),
+                (";", 1, 10),
+                ("print", 2, 3),
+                ("a", 2, 9),
+                (";", 2, 10),
+                // a multi-line string is reported where it starts
+                ("\"two\nlines\"", 3, 1),
+                ("x", 4, 8),
+                ("", 4, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_comments_on_the_side() {
+        let mut scanner = Scanner::new("// one\nprint 1; // two\n");
+        assert_eq!(scanner.by_ref().count(), 4);
+        let comments: Vec<(&str, u32, u32)> = scanner.comments().iter().map(|c| (c.text.as_str(), c.line, c.column)).collect();
+        assert_eq!(comments, vec![("// one", 1, 1), ("// two", 2, 10)]);
+    }
+
+    #[test]
+    fn the_first_error_ends_the_stream() {
+        let results: Vec<_> = Scanner::new("print 1; @ print 2;").collect();
+        assert_eq!(results.len(), 4);
+        let error = results[3].as_ref().unwrap_err();
+        assert_eq!((error.message.as_str(), error.line, error.column), ("Unexpected character.", 1, 10));
+    }
+
+    #[test]
+    fn an_unterminated_string_is_reported_where_it_starts() {
+        let results: Vec<_> = Scanner::new("print\n  \"open\nstill open").collect();
+        let error = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!((error.message.as_str(), error.line, error.column), ("Unterminated string.", 2, 3));
+    }
+
+    #[test]
+    fn the_parser_reports_a_scan_error_alone() {
+        let mut parser = Parser::new(Scanner::new("print (1 +\n\"open"));
+        assert!(parser.parse().is_none());
+        let errors: Vec<(&str, u32)> = parser.errors().iter().map(|e| (e.message.as_str(), e.line)).collect();
+        assert_eq!(errors, vec![("Unterminated string.", 2)]);
+    }
+}
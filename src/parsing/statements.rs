@@ -1,55 +1,86 @@
-use crate::errors::err::RuntimeErr;
-use crate::runtime::interpreter::Visitor;
+use super::ast::{Ast, StmtVisitor};
+use super::{expressions::ExprId, symbol::Symbol, tokens::Token};
 
-use super::{expressions::{Expr, Literal}, tokens::Token};
+/// index of a statement in its `Ast`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StmtId(pub u32);
 
-pub trait Stmt {
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr>;
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Dec(Dec),
+    Print(Print),
+    Expression(ExprStmt),
+    Test(Test),
 }
 
+impl Stmt {
+    pub fn accept<V: StmtVisitor + ?Sized>(&self, ast: &Ast, v: &mut V) -> V::Output {
+        match self {
+            Stmt::Dec(d) => v.visit_declaration(ast, d),
+            Stmt::Print(p) => v.visit_print_stmt(ast, p),
+            Stmt::Expression(e) => v.visit_expr_stmt(ast, e),
+            Stmt::Test(t) => v.visit_test(ast, t),
+        }
+    }
+
+    /// source line the statement starts on
+    pub fn line(&self) -> u32 {
+        match self {
+            Stmt::Dec(d) => d.name.line,
+            Stmt::Print(p) => p.line,
+            Stmt::Expression(e) => e.line,
+            Stmt::Test(t) => t.name.line,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Dec {
-    name: Token,
-    expr: Box<dyn Expr>,
+    pub name: Token,
+    pub symbol: Symbol,
+    pub expr: ExprId,
+}
+
+/// `test "name" { ... }`, only run by `lox test`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Test {
+    pub name: Token,
+    pub body: Vec<StmtId>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Print {
-    pub expr: Box<dyn Expr>,
+    pub expr: ExprId,
+    pub line: u32,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ExprStmt {
-    pub expr: Box<dyn Expr>,
+    pub expr: ExprId,
+    pub line: u32,
 }
 
 impl Dec {
-    pub fn new(name: Token, expr: Box<dyn Expr>) -> Self {
-        Dec { name, expr }
+    pub fn new(name: Token, expr: ExprId) -> Self {
+        let symbol = Symbol::intern(&name.lexeme);
+        Dec { name, symbol, expr }
     }
 }
 
-impl Print{
-    pub fn new(expr: Box<dyn Expr>) -> Self {
-        Print { expr }
+impl Test {
+    pub fn new(name: Token, body: Vec<StmtId>) -> Self {
+        Test { name, body }
     }
 }
 
-impl ExprStmt {
-    pub fn new(expr: Box<dyn Expr>) -> Self {
-        ExprStmt { expr }
+impl Print{
+    pub fn new(expr: ExprId, line: u32) -> Self {
+        Print { expr, line }
     }
 }
 
-impl Stmt for Dec{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        p.visit_declaration(self)
-    }
-}
-impl Stmt for Print{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        p.visit_print_stmt(self)
-    }
-}
-impl Stmt for ExprStmt{
-    fn accept(&self, p: &dyn Visitor<Literal>) -> Result<Literal, RuntimeErr> {
-        p.visit_expr_stmt(self)
+impl ExprStmt {
+    pub fn new(expr: ExprId, line: u32) -> Self {
+        ExprStmt { expr, line }
     }
 }
@@ -0,0 +1,92 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+thread_local! {
+    static TABLE: RefCell<Table> = RefCell::new(Table::default());
+}
+
+#[derive(Default)]
+struct Table {
+    ids: HashMap<Rc<str>, u32>,
+    names: Vec<Rc<str>>,
+}
+
+/// an interned string; equal text always gets the same id, so comparing and hashing are integer operations.
+/// interned text lives until the thread exits, so only names and literals from the source are interned,
+/// never strings built at run time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn intern(s: &str) -> Symbol {
+        TABLE.with(|table| {
+            let mut table = table.borrow_mut();
+            if let Some(id) = table.ids.get(s) {
+                return Symbol(*id);
+            }
+            let name: Rc<str> = Rc::from(s);
+            let id = table.names.len() as u32;
+            table.names.push(name.clone());
+            table.ids.insert(name, id);
+            Symbol(id)
+        })
+    }
+
+    pub fn as_str(self) -> Rc<str> {
+        TABLE.with(|table| table.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::rc::Rc;
+
+    use super::{Symbol, TABLE};
+    use crate::parsing::expressions::{Expr, ExprId, Literal, Value};
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::runtime::interpreter::Interpreter;
+
+    fn interned() -> usize {
+        TABLE.with(|table| table.borrow().names.len())
+    }
+
+    #[test]
+    fn equal_text_is_one_symbol() {
+        assert_eq!(Symbol::intern("name"), Symbol::intern("name"));
+        assert_ne!(Symbol::intern("name"), Symbol::intern("other"));
+        assert_eq!(&*Symbol::intern("name").as_str(), "name");
+    }
+
+    #[test]
+    fn equal_literals_share_their_text() {
+        let (ast, _) = Parser::new(Scanner::new("\"abc\" == \"abc\"")).parse_expression().unwrap();
+        let texts: Vec<Rc<str>> = (0..2)
+            .filter_map(|i| match ast.expr(ExprId(i)) {
+                Expr::Literal(Literal { val: Value::S(s), .. }) => Some(s.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts.len(), 2);
+        assert!(Rc::ptr_eq(&texts[0], &texts[1]));
+    }
+
+    #[test]
+    fn strings_built_at_run_time_are_not_interned() {
+        let program = Parser::new(Scanner::new("var a = \"x\";\nprint a + a + a;\nassert(a + \"y\" == \"xy\");\n")).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        let before = interned();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interned(), before);
+    }
+}
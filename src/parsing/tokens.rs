@@ -1,4 +1,5 @@
-#[derive(Debug, Clone, PartialEq)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
     LEFT_PAREN,
     RIGHT_PAREN,
@@ -45,13 +46,14 @@ pub enum TokenType {
     EOF,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub variant: TokenType,
     pub lexeme: String,
     pub string: Option<String>,
     pub int: Option<f64>,
     pub line: u32,
+    pub column: u32,
 }
 
 impl Token {
@@ -62,6 +64,7 @@ impl Token {
             line,
             string: None,
             int: None,
+            column: 0,
         }
     }
 
@@ -72,6 +75,7 @@ impl Token {
             line,
             string: None,
             int: Some(int),
+            column: 0,
         }
     }
 
@@ -82,10 +86,45 @@ impl Token {
             line,
             string: Some(string),
             int: None,
+            column: 0,
         }
     }
 }
 
+/// a token straight from the scanner, borrowing its text from the source.
+/// it becomes an owned `Token` only when something, such as a tree node, keeps it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawToken<'src> {
+    pub variant: TokenType,
+    pub lexeme: &'src str,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl RawToken<'_> {
+    pub fn to_token(self) -> Token {
+        let mut token = match self.variant {
+            TokenType::STRING => Token::new_str(
+                self.variant,
+                self.lexeme.to_owned(),
+                self.line,
+                self.lexeme[1..self.lexeme.len() - 1].to_owned(),
+            ),
+            TokenType::NUMBER => Token::new_int(self.variant, self.lexeme.to_owned(), self.line, self.lexeme.parse().unwrap()),
+            _ => Token::new(self.variant, self.lexeme.to_owned(), self.line),
+        };
+        token.column = self.column;
+        token
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub line: u32,
+    pub column: u32,
+}
+
 impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?} {} {}", self.variant, self.lexeme, self.line)
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+/// statement line hit counts, written out as an lcov tracefile
+pub struct Coverage {
+    lines: BTreeMap<u32, u64>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage { lines: BTreeMap::new() }
+    }
+
+    /// marks a line as executable even if it never runs
+    pub fn add_line(&mut self, line: u32) {
+        self.lines.entry(line).or_insert(0);
+    }
+
+    pub fn hit(&mut self, line: u32) {
+        *self.lines.entry(line).or_insert(0) += 1;
+    }
+
+    pub fn lcov(&self, source_file: &str) -> String {
+        let mut out = format!("TN:\nSF:{}\n", source_file);
+        for (line, hits) in self.lines.iter() {
+            out.push_str(&format!("DA:{},{}\n", line, hits));
+        }
+        let hit = self.lines.values().filter(|h| **h > 0).count();
+        out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", self.lines.len(), hit));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::runtime::interpreter::Interpreter;
+
+    fn lcov(source: &str, test: Option<&str>) -> String {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        interpreter.set_coverage(Coverage::new());
+        if let Some(name) = test {
+            interpreter.select_test(name);
+        }
+        interpreter.run(&program).unwrap();
+        interpreter.take_coverage().unwrap().lcov("a.lox")
+    }
+
+    #[test]
+    fn every_statement_line_is_counted() {
+        assert_eq!(
+            lcov("var a = 1;\nprint a;\n\nprint a + 1; print a + 2;", None),
+            "TN:\nSF:a.lox\nDA:1,1\nDA:2,1\nDA:4,2\nLF:3\nLH:3\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn statements_in_the_selected_test_are_hit() {
+        let source = "var a = 1;\ntest \"t\" {\n  print a;\n}\ntest \"u\" {\n  print a;\n}";
+        assert_eq!(
+            lcov(source, Some("t")),
+            "TN:\nSF:a.lox\nDA:1,1\nDA:2,1\nDA:3,1\nDA:5,1\nLF:4\nLH:4\nend_of_record\n"
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use crate::parsing::statements::Stmt;
+
+use super::interpreter::Interpreter;
+
+/// called by the interpreter before each statement; pausing is just not returning yet
+pub trait DebugHook {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+    Continue,
+    StepInto,
+    StepOver,
+    StepOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseReason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// breakpoint and stepping bookkeeping shared by the debugger front ends
+pub struct Stepper {
+    pub breakpoints: HashSet<u32>,
+    resume: Resume,
+    // call depth when execution was last resumed
+    depth: usize,
+    entry: bool,
+}
+
+impl Stepper {
+    pub fn new(stop_on_entry: bool) -> Self {
+        Stepper { breakpoints: HashSet::new(), resume: Resume::Continue, depth: 0, entry: stop_on_entry }
+    }
+
+    /// pauses before the first statement, keeping the breakpoints already set
+    pub fn stop_on_entry(&mut self) {
+        self.entry = true;
+    }
+
+    /// whether to pause before a statement on `line`, with `depth` frames active
+    pub fn check(&mut self, line: u32, depth: usize) -> Option<PauseReason> {
+        if self.entry {
+            self.entry = false;
+            return Some(PauseReason::Entry);
+        }
+        if self.breakpoints.contains(&line) {
+            return Some(PauseReason::Breakpoint);
+        }
+        let stepped = match self.resume {
+            Resume::Continue => false,
+            Resume::StepInto => true,
+            Resume::StepOver => depth <= self.depth,
+            Resume::StepOut => depth < self.depth,
+        };
+        if stepped {
+            return Some(PauseReason::Step);
+        }
+        None
+    }
+
+    pub fn resume(&mut self, how: Resume, depth: usize) {
+        self.resume = how;
+        self.depth = depth;
+    }
+}
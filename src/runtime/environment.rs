@@ -1,23 +1,78 @@
 use std::collections::HashMap;
-use crate::{parsing::{expressions::Expr, tokens::Token}, errors::err::RuntimeErr};
+use crate::{parsing::{expressions::Value, symbol::Symbol, tokens::Token}, errors::err::RuntimeErr};
 
-enum Ret {
-    E(Box<dyn Expr>)
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    // open block scopes, innermost last; locals are stored by slot, their names only kept for debuggers
+    scopes: Vec<Vec<(Symbol, Value)>>,
 }
 
-pub struct Environment {
-    values: HashMap<String, Ret>,
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
 }
 
 impl Environment {
-    fn define(&mut self, name: String, value: Ret){
-        self.values.insert(name, value);
+    pub fn new() -> Self {
+        Environment { values: HashMap::new(), scopes: Vec::new() }
+    }
+
+    /// defines a local in the innermost block, taking the next slot, or a global outside any block
+    pub fn define(&mut self, name: Symbol, value: Value){
+        match self.scopes.last_mut() {
+            Some(scope) => scope.push((name, value)),
+            None => {
+                self.values.insert(name, value);
+            }
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// every global, sorted by name
+    pub fn values(&self) -> Vec<(String, &Value)> {
+        let mut values: Vec<(String, &Value)> = self.values.iter().map(|(name, v)| (name.to_string(), v)).collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        values
+    }
+
+    /// the locals of each open block in slot order, innermost block first
+    pub fn locals(&self) -> Vec<Vec<(String, &Value)>> {
+        self.scopes
+            .iter()
+            .rev()
+            .map(|scope| scope.iter().map(|(name, v)| (name.to_string(), v)).collect())
+            .collect()
+    }
+
+    /// a local the resolver placed `depth` blocks out, at `slot`
+    pub fn get_at(&self, depth: usize, slot: usize) -> &Value {
+        &self.scopes[self.scopes.len() - 1 - depth][slot].1
+    }
+
+    /// `name` is only used for the error when `symbol` is unbound
+    pub fn get(&self, symbol: Symbol, name: &Token) -> Result<&Value, RuntimeErr> {
+        if self.values.contains_key(&symbol){
+            return Ok(self.values.get(&symbol).unwrap());
+        }
+        Err(RuntimeErr::new(format!("Undefined variable '{}'.", name.lexeme), name.clone()))
     }
 
-    fn get(&self, name: Token) -> Result<Ret, RuntimeErr> {
-        if self.values.contains_key(&name.lexeme){
-            return Ok(*self.values.get(&name.lexeme).unwrap());
+    /// looks `symbol` up by name through the open blocks and then the globals,
+    /// for expressions that were never resolved, such as one typed at the debugger
+    pub fn lookup(&self, symbol: Symbol, name: &Token) -> Result<&Value, RuntimeErr> {
+        for scope in self.scopes.iter().rev() {
+            if let Some((_, value)) = scope.iter().rev().find(|(s, _)| *s == symbol) {
+                return Ok(value);
+            }
         }
-        Err(RuntimeErr::new("Undefined variable".to_owned(), name))
+        self.get(symbol, name)
     }
 }
@@ -1,39 +1,200 @@
+// the arithmetic visitors predate the clippy gate
+#![allow(clippy::needless_return)]
+
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
 use crate::errors::err::RuntimeErr;
 
-use crate::parsing::expressions::{Binary, Grouping, Literal, Unary, Expr, Value};
-use crate::parsing::statements::{Stmt, Print, ExprStmt};
-use crate::parsing::tokens::{TokenType, Token};
-
-pub trait Visitor<T>{
-    fn visit_binary(&self, b: &Binary) -> Result<T, RuntimeErr>;
-    fn visit_grouping(&self, g: &Grouping) -> Result<T, RuntimeErr>;
-    fn visit_literal(&self, l: &Literal) -> Result<T, RuntimeErr>;
-    fn visit_unary(&self, u: &Unary) -> Result<T, RuntimeErr>;
-    fn visit_expr_stmt(&self, s: &ExprStmt) -> Result<T, RuntimeErr>;
-    fn visit_print_stmt(&self, s: &Print) -> Result<T, RuntimeErr>;
+use crate::parsing::ast::{Ast, ExprVisitor, Program, StmtVisitor};
+use crate::parsing::expressions::{Binary, Call, ExprId, Grouping, Literal, Unary, Variable, Value};
+use crate::parsing::statements::{Print, ExprStmt, Dec, StmtId, Test};
+use crate::parsing::resolver::{self, Resolution};
+use crate::parsing::symbol::Symbol;
+use crate::parsing::tokens::{Token, TokenType};
+use crate::runtime::coverage::Coverage;
+use crate::runtime::debugger::DebugHook;
+use crate::runtime::environment::Environment;
+use crate::runtime::natives::natives;
+use crate::runtime::profiler::Profiler;
+use crate::runtime::tracer::Tracer;
+
+/// an active call, the bottom one being the script itself
+pub struct Frame {
+    pub name: String,
+    pub line: u32,
+}
+
+/// calls deeper than this raise "Stack overflow." instead of exhausting the Rust stack, on both backends.
+/// without user functions only a native call can add a frame
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+pub struct Interpreter {
+    environment: Environment,
+    frames: Vec<Frame>,
+    max_call_depth: usize,
+    hook: Option<Box<dyn DebugHook>>,
+    output: Box<dyn Write>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    tracer: Option<Tracer>,
+    // the one test block that runs, see `select_test`
+    test: Option<String>,
+    // where the running program's locals live; `None` while evaluating an unresolved expression
+    resolution: Option<Resolution>,
 }
 
-pub struct Interpreter {}
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
 
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter {  }
+        let mut environment = Environment::new();
+        for native in natives() {
+            environment.define(Symbol::intern(native.name), Value::Native(native));
+        }
+
+        Interpreter {
+            environment,
+            frames: vec![Frame { name: "script".to_owned(), line: 0 }],
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            hook: None,
+            output: Box::new(io::stdout()),
+            profiler: None,
+            coverage: None,
+            tracer: None,
+            test: None,
+            resolution: None,
+        }
+    }
+
+    /// the most frames, the script's included, that may be active at once
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// runs the body of the named `test` block; all others are skipped as usual
+    pub fn select_test(&mut self, name: &str) {
+        self.test = Some(name.to_owned());
+    }
+
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
     }
 
-    pub fn interpret(&mut self, stmts: Vec<Box<dyn Stmt>>) {
-        for s in stmts {
-            let res = self.execute(s);
-            if res.is_err(){
-                let e = res.err().unwrap();
-                eprintln!("{:?}", e);
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// where `print` writes, stdout by default
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// lets a debugger look at every statement before it runs
+    pub fn set_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.hook = Some(hook);
+    }
+
+    /// innermost frame last
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// the environment chain as (scope name, bindings), innermost first
+    pub fn scopes(&self) -> Vec<(String, Vec<(String, Value)>)> {
+        let mut scopes = Vec::new();
+        for locals in self.environment.locals() {
+            let bindings = locals.into_iter().map(|(name, value)| (name, value.clone())).collect();
+            scopes.push(("block".to_owned(), bindings));
+        }
+        let mut bindings = Vec::new();
+        for (name, value) in self.environment.values() {
+            if let Value::Native(_) = value {
+                continue;
+            }
+            bindings.push((name, value.clone()));
+        }
+        scopes.push(("globals".to_owned(), bindings));
+        scopes
+    }
+
+    /// runs the program, handing a runtime error back for the caller to report
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeErr> {
+        let ast = program.ast.as_ref();
+        self.resolution = Some(resolver::resolve(program));
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter(&self.frames[0].name);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            for s in program.statements.iter() {
+                coverage.add_line(ast.stmt(*s).line());
+            }
+        }
+        let mut res = Ok(());
+        for s in program.statements.iter() {
+            if let Err(e) = self.execute(ast, *s) {
+                res = Err(e);
                 break;
             }
         }
-        
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit();
+        }
+        res
     }
 
-    fn evaluate(&self, e: &Box<dyn Expr>) -> Result<Literal, RuntimeErr> {
-        e.accept(self)
+    /// evaluates an expression from outside the running program, such as one typed at the debugger.
+    /// it was never resolved, so its variables are looked up by name
+    pub fn evaluate(&mut self, ast: &Ast, e: ExprId) -> Result<Literal, RuntimeErr> {
+        let resolution = mem::take(&mut self.resolution);
+        let res = ast.visit_expr(e, self);
+        self.resolution = resolution;
+        res
+    }
+
+    /// pushes a frame for a call made at `paren`, or fails once `max_call_depth` frames are active
+    fn enter_call(&mut self, name: &str, paren: &Token) -> Result<(), RuntimeErr> {
+        // the caller's frame shows where the call was made, even in a statement spanning several lines
+        if let Some(caller) = self.frames.last_mut() {
+            caller.line = paren.line;
+        }
+        if self.frames.len() >= self.max_call_depth {
+            let mut message = "Stack overflow.".to_owned();
+            for frame in self.frames.iter().rev() {
+                message.push_str(&format!("\n[line {}] in {}", frame.line, frame.name));
+            }
+            return Err(RuntimeErr::new(message, paren.clone()));
+        }
+        self.frames.push(Frame { name: name.to_owned(), line: paren.line });
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter(name);
+        }
+        Ok(())
+    }
+
+    /// pops the frame `enter_call` pushed
+    fn leave_call(&mut self) {
+        self.frames.pop();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit();
+        }
     }
 
     fn is_truthy(&self, expr: &Value) -> bool {
@@ -44,15 +205,38 @@ impl Interpreter {
         }
     }
 
-    fn execute(&mut self, stmt: Box<dyn Stmt>) -> Result<Literal, RuntimeErr>{
-        stmt.accept(self)
+    fn execute(&mut self, ast: &Ast, id: StmtId) -> Result<(), RuntimeErr>{
+        let stmt = ast.stmt(id);
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = stmt.line();
+        }
+        if let Some(mut hook) = self.hook.take() {
+            hook.before_statement(self, stmt);
+            self.hook = Some(hook);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.hit(stmt.line());
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.statement(stmt.line());
+        }
+
+        if self.profiler.is_none() {
+            return stmt.accept(ast, self);
+        }
+        self.profiler.as_mut().unwrap().statement_start(stmt.line());
+        let res = stmt.accept(ast, self);
+        self.profiler.as_mut().unwrap().statement_end();
+        res
     }
 }
 
-impl Visitor<Literal> for Interpreter {
-    fn visit_binary(&self, b: &Binary) -> Result<Literal, RuntimeErr> {
-        let left = b.left.accept(self);
-        let right = b.right.accept(self);
+impl ExprVisitor for Interpreter {
+    type Output = Result<Literal, RuntimeErr>;
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) -> Result<Literal, RuntimeErr> {
+        let left = ast.visit_expr(b.left, self);
+        let right = ast.visit_expr(b.right, self);
 
         if left.is_err(){
             return Err(left.err().unwrap());
@@ -102,7 +286,7 @@ impl Visitor<Literal> for Interpreter {
                 return Ok(
                     Literal::new(
                         b.operator.clone(),
-                        Value::S(x.clone() + &y.clone())
+                        Value::S(Rc::from(format!("{}{}", x, y)))
                     )
                 );
             }
@@ -181,14 +365,14 @@ impl Visitor<Literal> for Interpreter {
             } 
         }
     }
-    fn visit_grouping(&self, g: &Grouping) -> Result<Literal, RuntimeErr> {
-        g.expr.accept(self)
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) -> Result<Literal, RuntimeErr> {
+        ast.visit_expr(g.expr, self)
     } 
-    fn visit_literal(&self, l: &Literal) -> Result<Literal, RuntimeErr> {
+    fn visit_literal(&mut self, _ast: &Ast, l: &Literal) -> Result<Literal, RuntimeErr> {
         Ok(l.clone())
     }
-    fn visit_unary(&self, u: &Unary) -> Result<Literal, RuntimeErr> {
-        let possible = u.right.accept(self);
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) -> Result<Literal, RuntimeErr> {
+        let possible = ast.visit_expr(u.right, self);
         if possible.is_err(){
             let e = possible.err().unwrap();
             return Err(e);
@@ -226,14 +410,95 @@ impl Visitor<Literal> for Interpreter {
         }
     }
 
-    fn visit_expr_stmt(&self, s: &ExprStmt) -> Result<Literal, RuntimeErr> {
-        self.evaluate(&s.expr);
-        return Ok(Literal::default());
+    fn visit_variable(&mut self, _ast: &Ast, v: &Variable) -> Result<Literal, RuntimeErr> {
+        let value = match &self.resolution {
+            Some(resolution) => match resolution.local(v) {
+                Some(local) => self.environment.get_at(local.depth, local.slot),
+                None => self.environment.get(v.symbol, &v.name)?,
+            },
+            None => self.environment.lookup(v.symbol, &v.name)?,
+        };
+        let value = value.clone();
+        Ok(Literal::new(v.name.clone(), value))
+    }
+
+    fn visit_call(&mut self, ast: &Ast, c: &Call) -> Result<Literal, RuntimeErr> {
+        let callee = ast.visit_expr(c.callee, self)?;
+        let mut arguments = Vec::new();
+        for a in c.arguments.iter() {
+            arguments.push(ast.visit_expr(*a, self)?.val);
+        }
+
+        match callee.val {
+            Value::Native(native) => {
+                if arguments.len() != native.arity {
+                    return Err(
+                        RuntimeErr::new(
+                            format!("Expected {} arguments but got {}.", native.arity, arguments.len()),
+                            c.paren.clone()
+                        )
+                    );
+                }
+                // a call that overflows the stack never starts, so it is not traced
+                self.enter_call(native.name, &c.paren)?;
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.call(native.name, &arguments);
+                }
+                let res = (native.func)(&arguments);
+                self.leave_call();
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.ret(native.name, res.as_ref().ok());
+                }
+                match res {
+                    Ok(v) => Ok(Literal::new(c.paren.clone(), v)),
+                    Err(message) => Err(RuntimeErr::new(message, c.paren.clone())),
+                }
+            }
+            _ => Err(RuntimeErr::new("Can only call functions and classes.".to_owned(), c.paren.clone())),
+        }
+    }
+}
+
+impl StmtVisitor for Interpreter {
+    type Output = Result<(), RuntimeErr>;
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) -> Result<(), RuntimeErr> {
+        let value = ast.visit_expr(s.expr, self)?;
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.value(&value.val);
+        }
+        Ok(())
     }
 
-    fn visit_print_stmt(&self, s: &Print) -> Result<Literal, RuntimeErr> {
-        let value = self.evaluate(&s.expr);
-        println!("{:?}", value);
-        return Ok(Literal::default());
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) -> Result<(), RuntimeErr> {
+        let value = ast.visit_expr(s.expr, self)?;
+        _ = writeln!(self.output, "{}", value.val);
+        Ok(())
+    }
+
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) -> Result<(), RuntimeErr> {
+        let value = ast.visit_expr(d.expr, self)?;
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.assign(&d.name.lexeme, &value.val);
+        }
+        self.environment.define(d.symbol, value.val);
+        Ok(())
+    }
+
+    fn visit_test(&mut self, ast: &Ast, t: &Test) -> Result<(), RuntimeErr> {
+        if self.test.as_ref() != t.name.string.as_ref() {
+            return Ok(());
+        }
+        // the body is a block: its declarations are locals, gone once it finishes
+        self.environment.push_scope();
+        let mut res = Ok(());
+        for s in t.body.iter() {
+            res = self.execute(ast, *s);
+            if res.is_err() {
+                break;
+            }
+        }
+        self.environment.pop_scope();
+        res
     }
 }
@@ -1,2 +1,7 @@
 pub mod interpreter;
+pub mod natives;
 pub mod environment;
+pub mod coverage;
+pub mod debugger;
+pub mod profiler;
+pub mod tracer;
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub count: u64,
+    pub inclusive: Duration,
+    pub exclusive: Duration,
+}
+
+struct Active {
+    start: Instant,
+    // time spent in nested activations, subtracted for exclusive time
+    children: Duration,
+}
+
+struct ActiveCall {
+    name: String,
+    timing: Active,
+}
+
+struct ActiveLine {
+    line: u32,
+    timing: Active,
+}
+
+/// instrumenting profiler, fed by the interpreter as calls and statements start and finish
+pub struct Profiler {
+    calls: Vec<ActiveCall>,
+    statements: Vec<ActiveLine>,
+    functions: HashMap<String, Stats>,
+    lines: HashMap<u32, Stats>,
+    // "outer;inner;line N" -> exclusive time, for flamegraph tools
+    stacks: HashMap<String, Duration>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            calls: Vec::new(),
+            statements: Vec::new(),
+            functions: HashMap::new(),
+            lines: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    pub fn enter(&mut self, name: &str) {
+        self.calls.push(ActiveCall { name: name.to_owned(), timing: Active { start: Instant::now(), children: Duration::ZERO } });
+    }
+
+    pub fn exit(&mut self) {
+        let call = match self.calls.pop() {
+            Some(c) => c,
+            None => return,
+        };
+        let elapsed = call.timing.start.elapsed();
+        if let Some(parent) = self.calls.last_mut() {
+            parent.timing.children += elapsed;
+        }
+
+        // a recursive function's inclusive time is only counted by its outermost activation
+        let recursive = self.calls.iter().any(|c| c.name == call.name);
+        let stats = self.functions.entry(call.name).or_default();
+        stats.count += 1;
+        stats.exclusive += elapsed.saturating_sub(call.timing.children);
+        if !recursive {
+            stats.inclusive += elapsed;
+        }
+    }
+
+    pub fn statement_start(&mut self, line: u32) {
+        self.statements.push(ActiveLine { line, timing: Active { start: Instant::now(), children: Duration::ZERO } });
+    }
+
+    pub fn statement_end(&mut self) {
+        let statement = match self.statements.pop() {
+            Some(s) => s,
+            None => return,
+        };
+        let elapsed = statement.timing.start.elapsed();
+        let exclusive = elapsed.saturating_sub(statement.timing.children);
+        if let Some(parent) = self.statements.last_mut() {
+            parent.timing.children += elapsed;
+        }
+
+        let recursive = self.statements.iter().any(|s| s.line == statement.line);
+        let stats = self.lines.entry(statement.line).or_default();
+        stats.count += 1;
+        stats.exclusive += exclusive;
+        if !recursive {
+            stats.inclusive += elapsed;
+        }
+
+        let mut stack: Vec<String> = self.calls.iter().map(|c| c.name.clone()).collect();
+        stack.push(format!("line {}", statement.line));
+        *self.stacks.entry(stack.join(";")).or_default() += exclusive;
+    }
+
+    /// summary tables sorted by exclusive time, with `source` used to show each line
+    pub fn report(&self, source: &str) -> String {
+        let source: Vec<&str> = source.lines().collect();
+        let mut out = format!("{:<24} {:>8} {:>12} {:>12}\n", "function", "calls", "inclusive", "exclusive");
+
+        let mut functions: Vec<(&String, &Stats)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        for (name, stats) in functions {
+            out.push_str(&format!(
+                "{:<24} {:>8} {:>12} {:>12}\n",
+                name,
+                stats.count,
+                format_duration(stats.inclusive),
+                format_duration(stats.exclusive)
+            ));
+        }
+
+        out.push_str(&format!("\n{:<6} {:>8} {:>12} {:>12}  source\n", "line", "count", "inclusive", "exclusive"));
+        let mut lines: Vec<(&u32, &Stats)> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(b.0)));
+        for (line, stats) in lines {
+            let text = source.get(*line as usize - 1).map(|s| s.trim()).unwrap_or("");
+            out.push_str(&format!(
+                "{:<6} {:>8} {:>12} {:>12}  {}\n",
+                line,
+                stats.count,
+                format_duration(stats.inclusive),
+                format_duration(stats.exclusive),
+                text
+            ));
+        }
+        out
+    }
+
+    /// folded stacks, one `frame;frame;line N microseconds` per line
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.stacks.iter().collect();
+        stacks.sort();
+        stacks.iter().map(|(stack, time)| format!("{} {}\n", stack, time.as_micros())).collect()
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    format!("{:.3}ms", d.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::runtime::interpreter::Interpreter;
+
+    use super::Profiler;
+
+    fn profile(source: &str) -> Profiler {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        interpreter.set_profiler(Profiler::new());
+        interpreter.run(&program).unwrap();
+        interpreter.take_profiler().unwrap()
+    }
+
+    #[test]
+    fn native_calls_are_functions() {
+        let profiler = profile("assert(true);\nassert_eq(1, 1);\ngc();\nassert(false == false);\n");
+        let count = |name: &str| profiler.functions.get(name).map(|s| s.count);
+        assert_eq!(count("script"), Some(1));
+        assert_eq!(count("assert"), Some(2));
+        assert_eq!(count("assert_eq"), Some(1));
+        assert_eq!(count("gc"), Some(1));
+        assert!(profiler.report("").contains("assert_eq"));
+    }
+
+    #[test]
+    fn a_native_is_inside_the_script() {
+        let profiler = profile("assert(true);\n");
+        let script = profiler.functions["script"];
+        let assert = profiler.functions["assert"];
+        assert!(script.inclusive >= assert.inclusive);
+        assert!(script.exclusive <= script.inclusive);
+    }
+
+    #[test]
+    fn lines_are_counted() {
+        let profiler = profile("var a = 1;\nprint a;\nprint a;\n");
+        assert_eq!(profiler.lines[&1].count, 1);
+        assert_eq!(profiler.lines[&2].count, 1);
+        assert_eq!(profiler.lines[&3].count, 1);
+    }
+}
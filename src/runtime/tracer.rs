@@ -0,0 +1,120 @@
+use std::io::Write;
+
+use crate::parsing::expressions::Value;
+
+/// writes a line per executed statement, result, call and return
+pub struct Tracer {
+    out: Box<dyn Write>,
+    source: Vec<String>,
+    depth: usize,
+}
+
+impl Tracer {
+    pub fn new(source: &str, out: Box<dyn Write>) -> Self {
+        Tracer { out, source: source.lines().map(|l| l.trim().to_owned()).collect(), depth: 0 }
+    }
+
+    pub fn statement(&mut self, line: u32) {
+        let text = self.source.get(line as usize - 1).cloned().unwrap_or_default();
+        self.log(format!("[line {}] {}", line, text));
+    }
+
+    /// the value an expression statement produced
+    pub fn value(&mut self, value: &Value) {
+        self.log(format!("=> {}", repr(value)));
+    }
+
+    pub fn assign(&mut self, name: &str, value: &Value) {
+        self.log(format!("{} = {}", name, repr(value)));
+    }
+
+    pub fn call(&mut self, name: &str, arguments: &[Value]) {
+        let arguments: Vec<String> = arguments.iter().map(repr).collect();
+        self.depth += 1;
+        self.log(format!("call {}({})", name, arguments.join(", ")));
+    }
+
+    /// `value` is None when the call failed
+    pub fn ret(&mut self, name: &str, value: Option<&Value>) {
+        match value {
+            Some(v) => self.log(format!("return {} -> {}", name, repr(v))),
+            None => self.log(format!("return {} with error", name)),
+        }
+        self.depth -= 1;
+    }
+
+    fn log(&mut self, line: String) {
+        _ = writeln!(self.out, "{}{}", "  ".repeat(self.depth), line);
+    }
+}
+
+fn repr(value: &Value) -> String {
+    match value {
+        Value::S(s) => format!("{:?}", s),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+    use crate::runtime::interpreter::Interpreter;
+
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(source: &str, max_call_depth: usize) -> String {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        let captured = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        interpreter.set_max_call_depth(max_call_depth);
+        interpreter.set_tracer(Tracer::new(source, Box::new(Captured(captured.clone()))));
+        _ = interpreter.run(&program);
+        drop(interpreter);
+        let bytes = captured.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn calls_are_nested_under_their_statement() {
+        let source = "var a = \"x\";\na;\nassert_eq(assert(a == \"x\"), nil);\nassert(false);\nprint a;";
+        assert_eq!(
+            trace(source, 8),
+            "[line 1] var a = \"x\";\n\
+             a = \"x\"\n\
+             [line 2] a;\n\
+             => \"x\"\n\
+             [line 3] assert_eq(assert(a == \"x\"), nil);\n  \
+               call assert(true)\n  \
+               return assert -> nil\n  \
+               call assert_eq(nil, nil)\n  \
+               return assert_eq -> nil\n\
+             => nil\n\
+             [line 4] assert(false);\n  \
+               call assert(false)\n  \
+               return assert with error\n"
+        );
+    }
+
+    #[test]
+    fn an_overflowing_call_is_not_traced() {
+        assert_eq!(trace("assert(true);\nprint 1;", 1), "[line 1] assert(true);\n");
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+
+use crate::bytecode::{compiler, loxc};
+use crate::parsing::{checker, optimizer};
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+
+/// `lox compile file.lox [-o file.loxc]`, writes the program's bytecode for `lox run file.loxc`
+pub fn run(args: &[String]) -> i32 {
+    let mut input = None;
+    let mut output = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-o" | "--output" => {
+                i += 1;
+                output = args.get(i).cloned();
+            }
+            file => input = Some(file.to_owned()),
+        }
+        i += 1;
+    }
+    let input = match input {
+        Some(input) => input,
+        None => {
+            eprintln!("Usage: lox compile file.lox [-o file.loxc]");
+            return 64;
+        }
+    };
+    let output = output.unwrap_or(Path::new(&input).with_extension("loxc").display().to_string());
+
+    let source = match fs::read_to_string(&input) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not read {}: {}", input, e);
+            return 1;
+        }
+    };
+    let program = match Parser::new(Scanner::new(&source)).parse().filter(checker::report) {
+        Some(program) => program,
+        None => return 65,
+    };
+    let chunk = match compiler::compile(&optimizer::optimize(&program)) {
+        Some(chunk) => chunk,
+        None => return 65,
+    };
+    if let Err(e) = fs::write(&output, loxc::serialize(&chunk, &source)) {
+        eprintln!("could not write {}: {}", output, e);
+        return 1;
+    }
+    0
+}
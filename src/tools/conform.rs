@@ -0,0 +1,222 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// what a script's `// expect` annotations say should happen when it runs
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    // full stderr lines, e.g. "[line 2] Error at 'x': Expect expression."
+    errors: Vec<String>,
+    runtime_error: Option<(String, u32)>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expect = Expectations::default();
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as u32 + 1;
+            if let Some(rest) = after(line, "// expect: ") {
+                expect.output.push(rest.to_owned());
+            } else if let Some(rest) = after(line, "// expect runtime error: ") {
+                expect.runtime_error = Some((rest.to_owned(), line_number));
+            } else if let Some(rest) = after(line, "// Error") {
+                expect.errors.push(format!("[line {}] Error{}", line_number, rest));
+            } else if let Some(rest) = after(line, "// [") {
+                // `// [line 3] Error ...`, with an optional `java`/`c` prefix for one implementation only
+                let rest = rest.strip_prefix("java ").unwrap_or(rest);
+                if rest.starts_with("c ") {
+                    continue;
+                }
+                if let Some(error) = rest.strip_prefix("line ") {
+                    expect.errors.push(format!("[line {}", error));
+                }
+            }
+        }
+        expect
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            return 65;
+        }
+        if self.runtime_error.is_some() {
+            return 70;
+        }
+        0
+    }
+
+    /// every way the actual run differs from the annotations
+    fn check(&self, stdout: &str, stderr: &str, exit_code: i32) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        let output: Vec<&str> = stdout.lines().collect();
+        for i in 0..output.len().max(self.output.len()) {
+            match (self.output.get(i), output.get(i)) {
+                (Some(e), Some(a)) if e == a => {}
+                (Some(e), Some(a)) => failures.push(format!("expected output '{}', got '{}'", e, a)),
+                (Some(e), None) => failures.push(format!("missing expected output '{}'", e)),
+                (None, Some(a)) => failures.push(format!("unexpected output '{}'", a)),
+                (None, None) => {}
+            }
+        }
+
+        let errors: Vec<&str> = stderr.lines().filter(|l| !l.is_empty()).collect();
+        if let Some((message, line)) = &self.runtime_error {
+            let expected = [message.clone(), format!("[line {}]", line)];
+            if errors.len() < 2 || errors[0] != expected[0] || errors[1] != expected[1] {
+                failures.push(format!("expected runtime error '{}' at [line {}], got {:?}", message, line, errors));
+            }
+        } else {
+            for i in 0..errors.len().max(self.errors.len()) {
+                match (self.errors.get(i), errors.get(i)) {
+                    (Some(e), Some(a)) if e == a => {}
+                    (Some(e), Some(a)) => failures.push(format!("expected error '{}', got '{}'", e, a)),
+                    (Some(e), None) => failures.push(format!("missing expected error '{}'", e)),
+                    (None, Some(a)) => failures.push(format!("unexpected error '{}'", a)),
+                    (None, None) => {}
+                }
+            }
+        }
+
+        if exit_code != self.exit_code() {
+            failures.push(format!("expected exit code {}, got {}", self.exit_code(), exit_code));
+        }
+        failures
+    }
+}
+
+/// the rest of the line after `marker`, when the line's trailing comment starts with it.
+/// a `//` inside a string literal does not start a comment
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '/' if !in_string && line[i..].starts_with("//") => return line[i..].strip_prefix(marker),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `lox conform [--verbose] [--backend=tree|vm] <dirs or files>`, runs annotated scripts and reports how many behave as expected
+pub fn run(args: &[String]) -> i32 {
+    let verbose = args.iter().any(|a| a == "--verbose" || a == "-v");
+    let backend = args.iter().find(|a| a.starts_with("--backend="));
+    let mut files = Vec::new();
+    for path in args.iter().filter(|a| !a.starts_with('-')) {
+        super::collect_lox_files(Path::new(path), &mut files);
+    }
+    if files.is_empty() {
+        eprintln!("Usage: lox conform [--verbose] [--backend=tree|vm] <dirs or files>");
+        return 64;
+    }
+    files.sort();
+
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("could not find the lox executable: {}", e);
+            return 1;
+        }
+    };
+
+    let mut passed = 0;
+    for file in files.iter() {
+        let source = match fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("FAIL {}\n  could not read: {}", file.display(), e);
+                continue;
+            }
+        };
+        let expect = Expectations::parse(&source);
+
+        // a child process runs the script exactly as `lox <script>` would
+        let mut command = Command::new(&exe);
+        if let Some(backend) = backend {
+            command.arg("run").arg(backend);
+        }
+        let run = match command.arg(file).output() {
+            Ok(run) => run,
+            Err(e) => {
+                println!("FAIL {}\n  could not run: {}", file.display(), e);
+                continue;
+            }
+        };
+        let failures = expect.check(
+            &String::from_utf8_lossy(&run.stdout),
+            &String::from_utf8_lossy(&run.stderr),
+            run.status.code().unwrap_or(-1),
+        );
+
+        if failures.is_empty() {
+            passed += 1;
+            if verbose {
+                println!("PASS {}", file.display());
+            }
+        } else {
+            println!("FAIL {}", file.display());
+            for f in failures.iter().take(if verbose { usize::MAX } else { 5 }) {
+                println!("  {}", f);
+            }
+        }
+    }
+
+    let percent = passed as f64 * 100.0 / files.len() as f64;
+    println!("\n{} of {} scripts passed ({:.1}%)", passed, files.len(), percent);
+    if passed == files.len() {
+        return 0;
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotations_are_parsed() {
+        let expect = Expectations::parse(
+            "print 1; // expect: 1\nprint ; // Error at ';': Expect expression.\n// [java line 3] Error: a\n// [c line 3] Error: b\nprint x; // expect runtime error: Undefined variable 'x'.",
+        );
+        assert_eq!(expect.output, vec!["1"]);
+        assert_eq!(expect.errors, vec!["[line 2] Error at ';': Expect expression.", "[line 3] Error: a"]);
+        assert_eq!(expect.runtime_error, Some(("Undefined variable 'x'.".to_owned(), 5)));
+        assert_eq!(expect.exit_code(), 65);
+    }
+
+    #[test]
+    fn markers_inside_strings_are_not_annotations() {
+        let expect = Expectations::parse("print \"// expect: a\";\nprint \"// Error\"; // expect: // Error");
+        assert_eq!(expect.output, vec!["// Error"]);
+        assert!(expect.errors.is_empty());
+    }
+
+    #[test]
+    fn matching_runs_pass() {
+        let expect = Expectations::parse("print 1; // expect: 1\nprint x; // expect runtime error: Undefined variable 'x'.");
+        assert!(expect.check("1\n", "Undefined variable 'x'.\n[line 2]\n", 70).is_empty());
+    }
+
+    #[test]
+    fn differences_are_reported() {
+        let expect = Expectations::parse("print 1; // expect: 1\nprint 2; // expect: 2");
+        assert_eq!(
+            expect.check("1\n3\n4\n", "oops\n", 70),
+            vec![
+                "expected output '2', got '3'",
+                "unexpected output '4'",
+                "unexpected error 'oops'",
+                "expected exit code 0, got 70",
+            ]
+        );
+        let expect = Expectations::parse("print x; // expect runtime error: Undefined variable 'x'.");
+        assert_eq!(
+            expect.check("", "Undefined variable 'x'.\n[line 3]\n", 70),
+            vec!["expected runtime error 'Undefined variable 'x'.' at [line 1], got [\"Undefined variable 'x'.\", \"[line 3]\"]"]
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, StdinLock, Stdout, Write};
+use std::process;
+use std::rc::Rc;
+
+use crate::errors::err::RuntimeErr;
+use crate::parsing::ast::Program;
+use crate::parsing::checker;
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::statements::Stmt;
+use crate::runtime::debugger::{DebugHook, PauseReason, Resume, Stepper};
+use crate::runtime::interpreter::Interpreter;
+
+use super::json::Json;
+use super::rpc::{read_frame, write_message, Frame};
+
+const THREAD_ID: f64 = 1.0;
+
+/// `lox dap`, a Debug Adapter Protocol server on stdin/stdout.
+/// requests are only read while the program is paused or not running
+pub fn run() -> i32 {
+    let session = Rc::new(RefCell::new(Session::new()));
+
+    // wait for both launch and configurationDone before starting
+    loop {
+        let message = match session.borrow_mut().read() {
+            Some(m) => m,
+            None => return 1,
+        };
+        if let Action::Disconnect = session.borrow_mut().handle(&message, None) {
+            return 0;
+        }
+        let s = session.borrow();
+        if s.program.is_some() && s.configured {
+            break;
+        }
+    }
+
+    let program = session.borrow_mut().load();
+    let exit_code = match program {
+        Some(program) => {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_output(Box::new(OutputEvents(session.clone(), Vec::new())));
+            interpreter.set_hook(Box::new(Hook(session.clone())));
+            match interpreter.run(&program) {
+                Ok(()) => 0,
+                Err(e) => {
+                    session.borrow_mut().exception(&mut interpreter, &e);
+                    70
+                }
+            }
+        }
+        None => 65,
+    };
+
+    let mut s = session.borrow_mut();
+    s.event("exited", Json::object(vec![("exitCode", Json::Number(exit_code as f64))]));
+    s.event("terminated", Json::object(vec![]));
+    while let Some(message) = s.read() {
+        if let Action::Disconnect = s.handle(&message, None) {
+            break;
+        }
+    }
+    0
+}
+
+enum Action {
+    Stay,
+    Resume(Resume),
+    Disconnect,
+}
+
+struct Session {
+    input: StdinLock<'static>,
+    output: Stdout,
+    seq: i64,
+    stepper: Stepper,
+    program: Option<String>,
+    configured: bool,
+}
+
+impl Session {
+    fn new() -> Self {
+        Session {
+            input: io::stdin().lock(),
+            output: io::stdout(),
+            seq: 0,
+            stepper: Stepper::new(false),
+            program: None,
+            configured: false,
+        }
+    }
+
+    /// the next request, skipping frames that don't hold one; None at end of input
+    fn read(&mut self) -> Option<Json> {
+        loop {
+            match read_frame(&mut self.input)? {
+                Frame::Message(m) => return Some(m),
+                Frame::Malformed => eprintln!("ignoring a malformed message"),
+            }
+        }
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Json)>) {
+        self.seq += 1;
+        fields.insert(0, ("seq", Json::Number(self.seq as f64)));
+        if let Err(e) = write_message(&mut self.output, &Json::object(fields)) {
+            eprintln!("could not write to client: {}", e);
+        }
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        self.send(vec![
+            ("type", Json::str("response")),
+            ("request_seq", request.get("seq").clone()),
+            ("success", Json::Bool(true)),
+            ("command", request.get("command").clone()),
+            ("body", body),
+        ]);
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) {
+        self.send(vec![
+            ("type", Json::str("response")),
+            ("request_seq", request.get("seq").clone()),
+            ("success", Json::Bool(false)),
+            ("command", request.get("command").clone()),
+            ("message", Json::str(message)),
+        ]);
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(vec![("type", Json::str("event")), ("event", Json::str(event)), ("body", body)]);
+    }
+
+    // reads and parses the launched program, reporting errors as output
+    fn load(&mut self) -> Option<Program> {
+        let path = self.program.clone().unwrap_or_default();
+        let source = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                self.output_event("stderr", &format!("could not read {}: {}\n", path, e));
+                return None;
+            }
+        };
+        let mut parser = Parser::new(Scanner::new(&source));
+        let program = parser.parse();
+        for e in parser.errors() {
+            self.output_event("stderr", &format!("[line {}] Error: {}\n", e.line, e.message));
+        }
+        let program = program?;
+        let errors = checker::check(&program);
+        for (token, message) in errors.iter() {
+            self.output_event("stderr", &format!("[line {}] Error: {}\n", token.line, message));
+        }
+        errors.is_empty().then_some(program)
+    }
+
+    /// tells the client the program stopped, then serves its requests until it says how to go on
+    fn pause(&mut self, interpreter: &mut Interpreter, mut body: Vec<(&str, Json)>) -> Resume {
+        body.push(("threadId", Json::Number(THREAD_ID)));
+        body.push(("allThreadsStopped", Json::Bool(true)));
+        self.event("stopped", Json::object(body));
+
+        loop {
+            let message = match self.read() {
+                Some(m) => m,
+                None => process::exit(1),
+            };
+            match self.handle(&message, Some(interpreter)) {
+                Action::Stay => {}
+                Action::Resume(how) => return how,
+                Action::Disconnect => process::exit(0),
+            }
+        }
+    }
+
+    /// stops where a runtime error happened so its state can be looked at, then reports the error
+    fn exception(&mut self, interpreter: &mut Interpreter, error: &RuntimeErr) {
+        self.pause(
+            interpreter,
+            vec![
+                ("reason", Json::str("exception")),
+                ("description", Json::str("Runtime error")),
+                ("text", Json::str(error.message())),
+            ],
+        );
+        self.output_event("stderr", &format!("{}\n", error));
+    }
+
+    fn output_event(&mut self, category: &str, text: &str) {
+        self.event("output", Json::object(vec![("category", Json::str(category)), ("output", Json::str(text))]));
+    }
+
+    fn handle(&mut self, request: &Json, interpreter: Option<&mut Interpreter>) -> Action {
+        let args = request.get("arguments");
+        match request.get("command").as_str().unwrap_or("") {
+            "initialize" => {
+                self.respond(
+                    request,
+                    Json::object(vec![
+                        ("supportsConfigurationDoneRequest", Json::Bool(true)),
+                        ("supportsEvaluateForHovers", Json::Bool(true)),
+                    ]),
+                );
+                self.event("initialized", Json::object(vec![]));
+            }
+            "launch" => match args.get("program").as_str() {
+                Some(program) => {
+                    self.program = Some(program.to_owned());
+                    if args.get("stopOnEntry").as_bool().unwrap_or(false) {
+                        self.stepper.stop_on_entry();
+                    }
+                    self.respond(request, Json::Null);
+                }
+                None => self.fail(request, "launch needs a 'program' to run"),
+            },
+            "setBreakpoints" => {
+                self.stepper.breakpoints.clear();
+                let mut verified = Vec::new();
+                for bp in args.get("breakpoints").as_array() {
+                    if let Some(line) = bp.get("line").as_i64() {
+                        // lines start at 1, so anything else can never be hit
+                        let valid = u32::try_from(line).ok().filter(|l| *l > 0);
+                        if let Some(valid) = valid {
+                            self.stepper.breakpoints.insert(valid);
+                        }
+                        let mut breakpoint = vec![("verified", Json::Bool(valid.is_some())), ("line", Json::Number(line as f64))];
+                        if valid.is_none() {
+                            breakpoint.push(("message", Json::str("line numbers start at 1")));
+                        }
+                        verified.push(Json::object(breakpoint));
+                    }
+                }
+                self.respond(request, Json::object(vec![("breakpoints", Json::Array(verified))]));
+            }
+            "configurationDone" => {
+                self.configured = true;
+                self.respond(request, Json::Null);
+            }
+            "threads" => {
+                let thread = Json::object(vec![("id", Json::Number(THREAD_ID)), ("name", Json::str("main"))]);
+                self.respond(request, Json::object(vec![("threads", Json::Array(vec![thread]))]));
+            }
+            "stackTrace" => {
+                let frames = match interpreter {
+                    Some(i) => self.stack_frames(i),
+                    None => Vec::new(),
+                };
+                let total = Json::Number(frames.len() as f64);
+                self.respond(request, Json::object(vec![("stackFrames", Json::Array(frames)), ("totalFrames", total)]));
+            }
+            "scopes" => {
+                let mut scopes = Vec::new();
+                if let Some(i) = interpreter {
+                    for (n, (name, _)) in i.scopes().iter().enumerate() {
+                        scopes.push(Json::object(vec![
+                            ("name", Json::str(name)),
+                            ("variablesReference", Json::Number((n + 1) as f64)),
+                            ("expensive", Json::Bool(false)),
+                        ]));
+                    }
+                }
+                self.respond(request, Json::object(vec![("scopes", Json::Array(scopes))]));
+            }
+            "variables" => {
+                let reference = args.get("variablesReference").as_i64().unwrap_or(0);
+                let mut variables = Vec::new();
+                if let Some(i) = interpreter {
+                    if let Some((_, bindings)) = i.scopes().get((reference - 1) as usize) {
+                        for (name, value) in bindings {
+                            variables.push(Json::object(vec![
+                                ("name", Json::str(name)),
+                                ("value", Json::Str(value.to_string())),
+                                ("variablesReference", Json::Number(0.0)),
+                            ]));
+                        }
+                    }
+                }
+                self.respond(request, Json::object(vec![("variables", Json::Array(variables))]));
+            }
+            "evaluate" => {
+                let expression = args.get("expression").as_str().unwrap_or("");
+                match interpreter.map(|i| evaluate(i, expression)) {
+                    Some(Ok(result)) => self.respond(
+                        request,
+                        Json::object(vec![("result", Json::Str(result)), ("variablesReference", Json::Number(0.0))]),
+                    ),
+                    Some(Err(message)) => self.fail(request, &message),
+                    None => self.fail(request, "the program is not paused"),
+                }
+            }
+            "continue" => {
+                self.respond(request, Json::object(vec![("allThreadsContinued", Json::Bool(true))]));
+                return Action::Resume(Resume::Continue);
+            }
+            "next" => {
+                self.respond(request, Json::Null);
+                return Action::Resume(Resume::StepOver);
+            }
+            "stepIn" => {
+                self.respond(request, Json::Null);
+                return Action::Resume(Resume::StepInto);
+            }
+            "stepOut" => {
+                self.respond(request, Json::Null);
+                return Action::Resume(Resume::StepOut);
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Json::Null);
+                return Action::Disconnect;
+            }
+            command => self.fail(request, &format!("unsupported request '{}'", command)),
+        }
+        Action::Stay
+    }
+
+    fn stack_frames(&self, interpreter: &Interpreter) -> Vec<Json> {
+        let source = Json::object(vec![("path", Json::str(self.program.as_deref().unwrap_or("")))]);
+        let stack = interpreter.call_stack();
+        stack
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| {
+                Json::object(vec![
+                    ("id", Json::Number(id as f64)),
+                    ("name", Json::str(&frame.name)),
+                    ("source", source.clone()),
+                    ("line", Json::Number(frame.line as f64)),
+                    ("column", Json::Number(1.0)),
+                ])
+            })
+            .collect()
+    }
+}
+
+fn evaluate(interpreter: &mut Interpreter, text: &str) -> Result<String, String> {
+    let mut parser = Parser::new(Scanner::new(text));
+    let (ast, expr) = parser.parse_expression().ok_or_else(|| match parser.errors().first() {
+        Some(e) => e.message.clone(),
+        None => "could not parse expression".to_owned(),
+    })?;
+    match interpreter.evaluate(&ast, expr) {
+        Ok(value) => Ok(value.val.to_string()),
+        Err(e) => Err(e.message().clone()),
+    }
+}
+
+struct Hook(Rc<RefCell<Session>>);
+
+impl DebugHook for Hook {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) {
+        let mut session = self.0.borrow_mut();
+        let depth = interpreter.call_stack().len();
+        let reason = match session.stepper.check(stmt.line(), depth) {
+            Some(PauseReason::Entry) => "entry",
+            Some(PauseReason::Breakpoint) => "breakpoint",
+            Some(PauseReason::Step) => "step",
+            None => return,
+        };
+        let how = session.pause(interpreter, vec![("reason", Json::str(reason))]);
+        session.stepper.resume(how, depth);
+    }
+}
+
+/// forwards program output to the client as one `output` event per line
+struct OutputEvents(Rc<RefCell<Session>>, Vec<u8>);
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.1.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.1.is_empty() {
+            let text = String::from_utf8_lossy(&self.1).into_owned();
+            self.1.clear();
+            self.0.borrow_mut().output_event("stdout", &text);
+        }
+        Ok(())
+    }
+}
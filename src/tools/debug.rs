@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use crate::parsing::checker;
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::statements::Stmt;
+use crate::runtime::debugger::{DebugHook, PauseReason, Resume, Stepper};
+use crate::runtime::interpreter::Interpreter;
+
+const HELP: &str = "\
+break <line>     set a breakpoint (b)
+delete <line>    remove a breakpoint (d)
+breakpoints      list breakpoints
+step             step into the next statement (s)
+next             step over calls (n)
+finish           run until the current call returns
+continue         run until the next breakpoint (c)
+backtrace        show the call stack (bt)
+env              show variables in every scope
+print <expr>     evaluate an expression in the paused frame (p)
+list             show source around the current line (l)
+quit             stop debugging (q)";
+
+/// `lox debug file.lox`, an interactive debugger on stdin/stdout
+pub fn run(args: &[String]) -> i32 {
+    let path = match args.first() {
+        Some(p) => p,
+        None => {
+            eprintln!("Usage: lox debug file.lox");
+            return 64;
+        }
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not read {}: {}", path, e);
+            return 66;
+        }
+    };
+    let program = match Parser::new(Scanner::new(&source)).parse().filter(checker::report) {
+        Some(program) => program,
+        None => return 65,
+    };
+
+    println!("debugging {}, type 'help' for commands", path);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_hook(Box::new(Console {
+        stepper: Stepper::new(true),
+        source: source.lines().map(|l| l.to_owned()).collect(),
+        line: 0,
+    }));
+    // a runtime error ends the session the way it ends `lox run`
+    if let Err(e) = interpreter.run(&program) {
+        eprintln!("{}", e);
+        println!("program stopped by an error");
+        return 70;
+    }
+    println!("program finished");
+    0
+}
+
+struct Console {
+    stepper: Stepper,
+    source: Vec<String>,
+    line: u32,
+}
+
+impl Console {
+    fn show_line(&self, line: u32) {
+        let text = line.checked_sub(1).and_then(|l| self.source.get(l as usize)).map(|s| s.as_str()).unwrap_or("");
+        println!("{:>4} | {}", line, text);
+    }
+
+    fn list(&self) {
+        let first = self.line.saturating_sub(3).max(1);
+        for line in first..=(self.line + 3).min(self.source.len() as u32) {
+            let marker = if line == self.line { ">" } else { " " };
+            let text = &self.source[line as usize - 1];
+            println!("{}{:>4} | {}", marker, line, text);
+        }
+    }
+
+    fn evaluate(&self, interpreter: &mut Interpreter, text: &str) {
+        let (ast, expr) = match Parser::new(Scanner::new(text)).parse_expression() {
+            Some(e) => e,
+            None => return,
+        };
+        match interpreter.evaluate(&ast, expr) {
+            Ok(value) => println!("{}", value.val),
+            Err(e) => println!("error: {}", e.message()),
+        }
+    }
+
+    fn set_breakpoint(&mut self, arg: &str, add: bool) {
+        match arg.trim().parse::<u32>() {
+            Ok(0) => println!("line numbers start at 1"),
+            Ok(line) if add => {
+                self.stepper.breakpoints.insert(line);
+                println!("breakpoint at line {}", line);
+            }
+            Ok(line) => {
+                if !self.stepper.breakpoints.remove(&line) {
+                    println!("no breakpoint at line {}", line);
+                }
+            }
+            Err(_) => println!("expected a line number"),
+        }
+    }
+}
+
+impl DebugHook for Console {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) {
+        let depth = interpreter.call_stack().len();
+        let reason = match self.stepper.check(stmt.line(), depth) {
+            Some(r) => r,
+            None => return,
+        };
+        self.line = stmt.line();
+        match reason {
+            PauseReason::Entry => println!("paused at entry"),
+            PauseReason::Breakpoint => println!("hit breakpoint at line {}", self.line),
+            PauseReason::Step => {}
+        }
+        self.show_line(self.line);
+
+        let stdin = io::stdin();
+        loop {
+            print!("(lox-debug) ");
+            _ = io::stdout().flush();
+            let mut input = String::new();
+            if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+                // stdin closed, let the program run to completion
+                self.stepper.breakpoints.clear();
+                self.stepper.resume(Resume::Continue, depth);
+                return;
+            }
+            let input = input.trim();
+            let (command, arg) = input.split_once(' ').unwrap_or((input, ""));
+            match command {
+                "" => {}
+                "break" | "b" => self.set_breakpoint(arg, true),
+                "delete" | "d" => self.set_breakpoint(arg, false),
+                "breakpoints" => {
+                    let mut lines: Vec<&u32> = self.stepper.breakpoints.iter().collect();
+                    lines.sort();
+                    for line in lines {
+                        self.show_line(*line);
+                    }
+                }
+                "step" | "s" => return self.stepper.resume(Resume::StepInto, depth),
+                "next" | "n" => return self.stepper.resume(Resume::StepOver, depth),
+                "finish" => return self.stepper.resume(Resume::StepOut, depth),
+                "continue" | "c" => return self.stepper.resume(Resume::Continue, depth),
+                "backtrace" | "bt" => {
+                    for (i, frame) in interpreter.call_stack().iter().rev().enumerate() {
+                        println!("#{} {} at line {}", i, frame.name, frame.line);
+                    }
+                }
+                "env" => {
+                    for (scope, bindings) in interpreter.scopes() {
+                        println!("{}:", scope);
+                        for (name, value) in bindings {
+                            println!("  {} = {}", name, value);
+                        }
+                    }
+                }
+                "print" | "p" => self.evaluate(interpreter, arg),
+                "list" | "l" => self.list(),
+                "help" | "h" => println!("{}", HELP),
+                "quit" | "q" => process::exit(0),
+                _ => println!("unknown command '{}', type 'help' for commands", command),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::debugger::Stepper;
+
+    use super::Console;
+
+    fn console() -> Console {
+        Console { stepper: Stepper::new(false), source: vec!["print 1;".to_owned()], line: 1 }
+    }
+
+    #[test]
+    fn line_zero_is_not_a_breakpoint() {
+        let mut console = console();
+        console.set_breakpoint("0", true);
+        assert!(console.stepper.breakpoints.is_empty());
+        console.set_breakpoint("1", true);
+        assert!(console.stepper.breakpoints.contains(&1));
+    }
+
+    #[test]
+    fn showing_lines_outside_the_source_does_not_panic() {
+        let console = console();
+        console.show_line(0);
+        console.show_line(2);
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+
+use crate::bytecode::chunk::Chunk;
+use crate::bytecode::debug::disassemble_chunk;
+use crate::bytecode::vm::VM;
+use crate::bytecode::{compiler, loxc};
+use crate::parsing::{checker, optimizer};
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+
+/// `lox disasm [--trace-exec] file.lox|file.loxc`
+pub fn run(args: &[String]) -> i32 {
+    let trace_exec = args.iter().any(|a| a == "--trace-exec");
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    if files.len() != 1 {
+        eprintln!("Usage: lox disasm [--trace-exec] file.lox|file.loxc");
+        return 64;
+    }
+    let path = files[0];
+    let chunk = match load(path) {
+        Ok(chunk) => chunk,
+        Err(code) => return code,
+    };
+
+    let name = Path::new(path).file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or(path.clone());
+    print!("{}", disassemble_chunk(&chunk, &name));
+    if !trace_exec {
+        return 0;
+    }
+
+    let mut vm = VM::new();
+    vm.set_trace_exec(true);
+    if let Err(e) = vm.run(&chunk) {
+        eprintln!("{}", e);
+        return 70;
+    }
+    0
+}
+
+fn load(path: &str) -> Result<Chunk, i32> {
+    if path.ends_with(".loxc") {
+        let bytes = fs::read(path).map_err(|e| {
+            eprintln!("could not read {}: {}", path, e);
+            66
+        })?;
+        return match loxc::deserialize(&bytes) {
+            Ok(compiled) => Ok(compiled.chunk),
+            Err(e) => {
+                eprintln!("could not load {}: {}", path, e);
+                Err(65)
+            }
+        };
+    }
+
+    let source = fs::read_to_string(path).map_err(|e| {
+        eprintln!("could not read {}: {}", path, e);
+        66
+    })?;
+    let program = Parser::new(Scanner::new(&source)).parse().filter(checker::report).ok_or(65)?;
+    compiler::compile(&optimizer::optimize(&program)).ok_or(65)
+}
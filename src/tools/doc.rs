@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::statements::Stmt;
+use crate::parsing::tokens::Comment;
+
+#[derive(PartialEq)]
+enum Format {
+    Markdown,
+    Html,
+}
+
+/// a documented declaration
+struct Item {
+    name: String,
+    kind: &'static str,
+    signature: String,
+    docs: Vec<String>,
+}
+
+/// everything documented in one source file
+struct Page {
+    title: String,
+    slug: String,
+    docs: Vec<String>,
+    items: Vec<Item>,
+}
+
+/// `lox doc [--format markdown|html] [--out <dir>] files`
+pub fn run(args: &[String]) -> i32 {
+    let mut format = Format::Markdown;
+    let mut out = "doc".to_owned();
+    let mut files = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--format" => {
+                i += 1;
+                format = match args.get(i).map(|s| s.as_str()) {
+                    Some("markdown") | Some("md") => Format::Markdown,
+                    Some("html") => Format::Html,
+                    _ => {
+                        eprintln!("--format expects markdown or html");
+                        return 64;
+                    }
+                };
+            }
+            "--out" => {
+                i += 1;
+                match args.get(i) {
+                    Some(dir) => out = dir.clone(),
+                    None => {
+                        eprintln!("--out expects a directory");
+                        return 64;
+                    }
+                }
+            }
+            flag if flag.starts_with("--") => {
+                eprintln!("unknown option {}", flag);
+                return 64;
+            }
+            file => files.push(file.to_owned()),
+        }
+        i += 1;
+    }
+    if files.is_empty() {
+        eprintln!("Usage: lox doc [--format markdown|html] [--out <dir>] files");
+        return 64;
+    }
+
+    let mut pages = Vec::new();
+    for file in files.iter() {
+        match document(file) {
+            Some(page) => pages.push(page),
+            None => return 65,
+        }
+    }
+
+    // pages are named after the file, so `a/util.lox` and `b/util.lox` would overwrite each other
+    for (i, page) in pages.iter().enumerate() {
+        if let Some(j) = pages[..i].iter().position(|p| p.slug == page.slug) {
+            eprintln!("{} and {} would both be documented as {}, rename one", files[j], files[i], page.slug);
+            return 64;
+        }
+    }
+
+    // symbol name -> slug of the page declaring it, for cross-links
+    let mut index = HashMap::new();
+    for page in pages.iter() {
+        for item in page.items.iter() {
+            index.entry(item.name.clone()).or_insert(page.slug.clone());
+        }
+    }
+
+    if let Err(e) = fs::create_dir_all(&out) {
+        eprintln!("could not create {}: {}", out, e);
+        return 1;
+    }
+    let mut written = Vec::new();
+    for page in pages.iter() {
+        let (name, text) = match format {
+            Format::Markdown => (format!("{}.md", page.slug), markdown(page, &index)),
+            Format::Html => (format!("{}.html", page.slug), html(page, &index)),
+        };
+        written.push((name, text));
+    }
+    if format == Format::Html {
+        written.push(("index.html".to_owned(), html_index(&pages)));
+    }
+    for (name, text) in written {
+        let path = Path::new(&out).join(name);
+        if let Err(e) = fs::write(&path, text) {
+            eprintln!("could not write {}: {}", path.display(), e);
+            return 1;
+        }
+    }
+    0
+}
+
+fn document(path: &str) -> Option<Page> {
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not read {}: {}", path, e);
+            return None;
+        }
+    };
+    let mut parser = Parser::new(Scanner::new(&source));
+    let program = parser.parse()?;
+
+    // the top level declarations worth documenting
+    let found = program.statements.iter().filter_map(|s| match program.ast.stmt(*s) {
+        Stmt::Dec(d) => Some(("variable", &d.name)),
+        Stmt::Print(_) | Stmt::Expression(_) | Stmt::Test(_) => None,
+    });
+
+    let comments = parser.scanner().comments();
+    let lines: Vec<&str> = source.lines().collect();
+    let items = found
+        .map(|(kind, name)| Item {
+            name: name.lexeme.clone(),
+            kind,
+            signature: name.line.checked_sub(1).and_then(|l| lines.get(l as usize)).map(|l| l.trim().to_owned()).unwrap_or_default(),
+            docs: doc_comment(comments, name.line),
+        })
+        .collect();
+
+    let file_docs = comments.iter().filter_map(|c| c.text.strip_prefix("//!")).map(|t| t.trim().to_owned()).collect();
+    let title = Path::new(path).file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or(path.to_owned());
+    let slug = title.trim_end_matches(".lox").to_owned();
+    Some(Page { title, slug, docs: file_docs, items })
+}
+
+// the run of `///` lines directly above `line`, `////` separators are not docs
+fn doc_comment(comments: &[Comment], line: u32) -> Vec<String> {
+    let mut docs = Vec::new();
+    let mut expected = line.saturating_sub(1);
+    for c in comments.iter().rev().skip_while(|c| c.line >= line) {
+        match c.text.strip_prefix("///").filter(|text| !text.starts_with('/')) {
+            Some(text) if c.line == expected => {
+                docs.push(text.strip_prefix(' ').unwrap_or(text).to_owned());
+                expected -= 1;
+            }
+            _ => break,
+        }
+    }
+    docs.reverse();
+    docs
+}
+
+// rewrites `[name]` for known symbols with `link(name, slug)`
+fn cross_link(text: &str, index: &HashMap<String, String>, link: &dyn Fn(&str, &str) -> String) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after.find(']') {
+            Some(close) if index.contains_key(&after[..close]) => {
+                let name = &after[..close];
+                out.push_str(&link(name, &index[name]));
+                rest = &after[close + 1..];
+            }
+            _ => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn markdown(page: &Page, index: &HashMap<String, String>) -> String {
+    let link = |name: &str, slug: &str| format!("[`{}`]({}.md#{})", name, slug, name.to_lowercase());
+    let mut out = format!("# {}\n\n", page.title);
+    for line in page.docs.iter() {
+        out.push_str(&cross_link(line, index, &link));
+        out.push('\n');
+    }
+    if !page.docs.is_empty() {
+        out.push('\n');
+    }
+    for item in page.items.iter() {
+        out.push_str(&format!("## {}\n\n*{}*\n\n```lox\n{}\n```\n\n", item.name, item.kind, item.signature));
+        for line in item.docs.iter() {
+            out.push_str(&cross_link(line, index, &link));
+            out.push('\n');
+        }
+        if !item.docs.is_empty() {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn html(page: &Page, index: &HashMap<String, String>) -> String {
+    let link = |name: &str, slug: &str| format!("<a href=\"{}.html#{}\"><code>{}</code></a>", slug, name, name);
+    let paragraph = |lines: &[String]| -> String {
+        if lines.is_empty() {
+            return String::new();
+        }
+        format!("<p>{}</p>\n", cross_link(&escape_html(&lines.join("\n")), index, &link))
+    };
+
+    let mut body = format!("<h1>{}</h1>\n{}", escape_html(&page.title), paragraph(&page.docs));
+    body.push_str("<ul>\n");
+    for item in page.items.iter() {
+        body.push_str(&format!("<li><a href=\"#{0}\">{0}</a></li>\n", escape_html(&item.name)));
+    }
+    body.push_str("</ul>\n");
+    for item in page.items.iter() {
+        body.push_str(&format!(
+            "<h2 id=\"{0}\">{0}</h2>\n<p><em>{1}</em></p>\n<pre><code>{2}</code></pre>\n{3}",
+            escape_html(&item.name),
+            item.kind,
+            escape_html(&item.signature),
+            paragraph(&item.docs)
+        ));
+    }
+    html_page(&page.title, &body)
+}
+
+fn html_index(pages: &[Page]) -> String {
+    let mut body = "<h1>Documentation</h1>\n<ul>\n".to_owned();
+    for page in pages {
+        body.push_str(&format!("<li><a href=\"{}.html\">{}</a><ul>\n", page.slug, escape_html(&page.title)));
+        for item in page.items.iter() {
+            body.push_str(&format!("<li><a href=\"{}.html#{1}\">{1}</a></li>\n", page.slug, escape_html(&item.name)));
+        }
+        body.push_str("</ul></li>\n");
+    }
+    body.push_str("</ul>\n");
+    html_page("Documentation", &body)
+}
+
+fn html_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}<p><a href=\"index.html\">index</a></p>\n</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn comments(lines: &[(u32, &str)]) -> Vec<Comment> {
+        lines.iter().map(|(line, text)| Comment { text: text.to_string(), line: *line, column: 1 }).collect()
+    }
+
+    #[test]
+    fn collects_the_run_directly_above() {
+        let comments = comments(&[(1, "/// unrelated"), (3, "/// first"), (4, "///second")]);
+        assert_eq!(doc_comment(&comments, 5), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn separators_end_the_run() {
+        let comments = comments(&[(1, "/// kept out"), (2, "////////"), (3, "/// docs")]);
+        assert_eq!(doc_comment(&comments, 4), vec!["docs"]);
+        assert!(doc_comment(&comments, 3).is_empty());
+    }
+
+    #[test]
+    fn plain_comments_are_not_docs() {
+        let comments = comments(&[(1, "// note")]);
+        assert!(doc_comment(&comments, 2).is_empty());
+    }
+
+    fn page() -> Page {
+        Page {
+            title: "util.lox".to_owned(),
+            slug: "util".to_owned(),
+            docs: vec!["helpers, see [limit]".to_owned()],
+            items: vec![Item {
+                name: "limit".to_owned(),
+                kind: "variable",
+                signature: "var limit = 3;".to_owned(),
+                docs: vec!["at most <3> [retries]".to_owned()],
+            }],
+        }
+    }
+
+    fn index() -> HashMap<String, String> {
+        HashMap::from([("limit".to_owned(), "util".to_owned()), ("other".to_owned(), "more".to_owned())])
+    }
+
+    #[test]
+    fn only_known_names_are_linked() {
+        let link = |name: &str, slug: &str| format!("<{}@{}>", name, slug);
+        assert_eq!(cross_link("[limit] and [other], [a] [b", &index(), &link), "<limit@util> and <other@more>, [a] [b");
+        assert_eq!(cross_link("[[limit]]", &index(), &link), "[<limit@util>]");
+    }
+
+    #[test]
+    fn markdown_pages() {
+        assert_eq!(
+            markdown(&page(), &index()),
+            "# util.lox\n\nhelpers, see [`limit`](util.md#limit)\n\n## limit\n\n*variable*\n\n```lox\nvar limit = 3;\n```\n\nat most <3> [retries]\n\n"
+        );
+    }
+
+    #[test]
+    fn html_pages() {
+        let html = html(&page(), &index());
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>util.lox</title>\n"));
+        assert!(html.contains(
+            "<h1>util.lox</h1>\n<p>helpers, see <a href=\"util.html#limit\"><code>limit</code></a></p>\n<ul>\n<li><a href=\"#limit\">limit</a></li>\n</ul>\n"
+        ));
+        assert!(html.contains(
+            "<h2 id=\"limit\">limit</h2>\n<p><em>variable</em></p>\n<pre><code>var limit = 3;</code></pre>\n<p>at most &lt;3&gt; [retries]</p>\n"
+        ));
+        assert!(html_index(&[page()]).contains("<li><a href=\"util.html\">util.lox</a><ul>\n<li><a href=\"util.html#limit\">limit</a></li>\n</ul></li>\n"));
+    }
+}
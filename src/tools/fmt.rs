@@ -0,0 +1,284 @@
+use std::fs;
+use std::io::{self, Read};
+
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::tokens::{Comment, RawToken, TokenType};
+
+const INDENT: &str = "    ";
+
+/// `lox fmt [--check] [files]`, formats stdin to stdout when no files are given
+pub fn run(args: &[String]) -> i32 {
+    let check = args.iter().any(|a| a == "--check");
+    let paths: Vec<&String> = args.iter().filter(|a| *a != "--check").collect();
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if io::stdin().read_to_string(&mut source).is_err() {
+            eprintln!("could not read stdin");
+            return 1;
+        }
+        return match format_source(&source) {
+            None => 1,
+            Some(formatted) if check => (formatted != source) as i32,
+            Some(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+        };
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("could not read {}: {}", path, e);
+                code = 1;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source) {
+            Some(f) => f,
+            None => {
+                eprintln!("could not format {}", path);
+                code = 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", path);
+            code = 1;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("could not write {}: {}", path, e);
+            code = 1;
+        }
+    }
+    code
+}
+
+/// re-emits `source` in canonical style, or None if it does not scan and parse
+pub fn format_source(source: &str) -> Option<String> {
+    Parser::new(Scanner::new(source)).parse()?;
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.by_ref().collect::<Result<Vec<RawToken>, _>>().ok()?;
+    Some(Formatter::new(tokens, scanner.comments().to_vec()).format())
+}
+
+struct Formatter<'src> {
+    tokens: Vec<RawToken<'src>>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    lines: Vec<String>,
+    line: String,
+    // source line of whatever was written last, used to keep trailing comments and blank lines
+    last_line: u32,
+    depth: usize,
+    parens: usize,
+    newline: bool,
+    ends_with_comment: bool,
+    last_variant: Option<TokenType>,
+}
+
+impl<'src> Formatter<'src> {
+    fn new(tokens: Vec<RawToken<'src>>, comments: Vec<Comment>) -> Self {
+        Formatter {
+            tokens,
+            comments,
+            next_comment: 0,
+            lines: Vec::new(),
+            line: String::new(),
+            last_line: 0,
+            depth: 0,
+            parens: 0,
+            newline: false,
+            ends_with_comment: false,
+            last_variant: None,
+        }
+    }
+
+    fn format(mut self) -> String {
+        for i in 0..self.tokens.len() {
+            let line = self.tokens[i].line;
+            if self.tokens[i].variant == TokenType::EOF {
+                self.flush_comments(u32::MAX);
+                break;
+            }
+            self.flush_comments(line);
+            self.write_token(i);
+        }
+        if !self.line.is_empty() {
+            self.lines.push(self.line);
+        }
+        if self.lines.is_empty() {
+            return String::new();
+        }
+        self.lines.join("\n") + "\n"
+    }
+
+    fn flush_comments(&mut self, before: u32) {
+        while self.next_comment < self.comments.len() && self.comments[self.next_comment].line < before {
+            let comment = self.comments[self.next_comment].clone();
+            self.next_comment += 1;
+
+            if !self.line.is_empty() && comment.line == self.last_line {
+                self.line.push_str("  ");
+                self.line.push_str(&comment.text);
+            } else {
+                self.start_line(comment.line, true);
+                self.line.push_str(&comment.text);
+            }
+            self.last_line = comment.line;
+            self.newline = true;
+            self.ends_with_comment = true;
+        }
+    }
+
+    fn start_line(&mut self, src_line: u32, allow_blank: bool) {
+        let continuation = !self.line.is_empty() && self.in_statement();
+        if !self.line.is_empty() {
+            let finished = std::mem::take(&mut self.line);
+            let opens_block = finished.ends_with('{');
+            self.lines.push(finished);
+            if allow_blank && !opens_block && !continuation && src_line > self.last_line + 1 {
+                self.lines.push(String::new());
+            }
+        }
+        let depth = if continuation { self.depth + 1 } else { self.depth };
+        self.line = INDENT.repeat(depth);
+    }
+
+    // true when the last token written leaves a statement unfinished
+    fn in_statement(&self) -> bool {
+        self.parens > 0
+            || !matches!(
+                self.last_variant,
+                None | Some(TokenType::SEMICOLON) | Some(TokenType::LEFT_BRACE) | Some(TokenType::RIGHT_BRACE)
+            )
+    }
+
+    fn write_token(&mut self, i: usize) {
+        let token = self.tokens[i];
+
+        if token.variant == TokenType::RIGHT_BRACE {
+            self.depth = self.depth.saturating_sub(1);
+        }
+
+        let joins_else = token.variant == TokenType::ELSE
+            && i > 0
+            && self.tokens[i - 1].variant == TokenType::RIGHT_BRACE
+            && !self.ends_with_comment;
+
+        if self.newline && !joins_else {
+            let allow_blank = token.variant != TokenType::RIGHT_BRACE;
+            self.start_line(token.line, allow_blank);
+        } else if self.line.is_empty() {
+            self.line = INDENT.repeat(self.depth);
+        } else if joins_else || self.needs_space(i) {
+            self.line.push(' ');
+        }
+        self.newline = false;
+        self.ends_with_comment = false;
+
+        self.line.push_str(token.lexeme);
+        // a multi-line string ends below the line it starts on
+        self.last_line = token.line + token.lexeme.matches('\n').count() as u32;
+        self.last_variant = Some(token.variant);
+
+        match token.variant {
+            TokenType::LEFT_BRACE => {
+                self.depth += 1;
+                self.newline = true;
+            }
+            TokenType::RIGHT_BRACE => {
+                self.newline = true;
+            }
+            TokenType::SEMICOLON if self.parens == 0 => {
+                self.newline = true;
+            }
+            TokenType::LEFT_PAREN => {
+                self.parens += 1;
+            }
+            TokenType::RIGHT_PAREN => {
+                self.parens = self.parens.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+
+    fn needs_space(&self, i: usize) -> bool {
+        let prev = &self.tokens[i - 1].variant;
+        match self.tokens[i].variant {
+            TokenType::SEMICOLON | TokenType::COMMA | TokenType::RIGHT_PAREN | TokenType::DOT => {
+                return false;
+            }
+            TokenType::LEFT_PAREN if matches!(prev, TokenType::IDENTIFIER | TokenType::RIGHT_PAREN) => {
+                return false;
+            }
+            _ => {}
+        }
+        match prev {
+            TokenType::LEFT_PAREN | TokenType::DOT => false,
+            TokenType::BANG | TokenType::MINUS => !self.is_unary(i - 1),
+            _ => true,
+        }
+    }
+
+    fn is_unary(&self, i: usize) -> bool {
+        if self.tokens[i].variant == TokenType::BANG || i == 0 {
+            return true;
+        }
+        !matches!(
+            self.tokens[i - 1].variant,
+            TokenType::IDENTIFIER
+                | TokenType::NUMBER
+                | TokenType::STRING
+                | TokenType::RIGHT_PAREN
+                | TokenType::TRUE
+                | TokenType::FALSE
+                | TokenType::NIL
+                | TokenType::THIS
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::format_source;
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fmt");
+        let mut checked = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let once = format_source(&source).unwrap_or_else(|| panic!("{} does not parse", path.display()));
+            assert_eq!(format_source(&once).as_deref(), Some(once.as_str()), "{}", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn a_comment_after_a_multi_line_string_stays_on_its_line() {
+        let formatted = format_source("print \"a\nb\"; // after\nprint 1;\n").unwrap();
+        assert_eq!(formatted, "print \"a\nb\";  // after\nprint 1;\n");
+    }
+
+    #[test]
+    fn keeps_comments_and_collapses_blank_lines() {
+        let formatted = format_source("var x=1; // one\n\n\n// two\nprint x;\n").unwrap();
+        assert_eq!(formatted, "var x = 1;  // one\n\n// two\nprint x;\n");
+    }
+
+    #[test]
+    fn refuses_source_that_does_not_parse() {
+        assert_eq!(format_source("var x = ;"), None);
+    }
+}
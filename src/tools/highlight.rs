@@ -0,0 +1,249 @@
+use std::fs;
+use std::io::{self, Read};
+
+use crate::parsing::expressions::Value;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::tokens::{Comment, RawToken, TokenType};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    Ansi,
+    Html,
+}
+
+/// what a span of source is colored as
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Keyword,
+    Literal,
+    String,
+    Number,
+    Comment,
+    Operator,
+    Plain,
+}
+
+impl Class {
+    fn of(token: &RawToken) -> Class {
+        match token.variant {
+            TokenType::TRUE | TokenType::FALSE | TokenType::NIL => Class::Literal,
+            TokenType::STRING => Class::String,
+            TokenType::NUMBER => Class::Number,
+            TokenType::IDENTIFIER => Class::Plain,
+            TokenType::LEFT_PAREN
+            | TokenType::RIGHT_PAREN
+            | TokenType::LEFT_BRACE
+            | TokenType::RIGHT_BRACE
+            | TokenType::COMMA
+            | TokenType::DOT
+            | TokenType::SEMICOLON
+            | TokenType::EOF => Class::Plain,
+            TokenType::MINUS
+            | TokenType::PLUS
+            | TokenType::SLASH
+            | TokenType::STAR
+            | TokenType::BANG
+            | TokenType::BANG_EQUAL
+            | TokenType::EQUAL
+            | TokenType::EQUAL_EQUAL
+            | TokenType::GREATER
+            | TokenType::GREATER_EQUAL
+            | TokenType::LESS
+            | TokenType::LESS_EQUAL => Class::Operator,
+            _ => Class::Keyword,
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Class::Keyword => "\x1b[35m",
+            Class::Literal => "\x1b[34m",
+            Class::String => "\x1b[32m",
+            Class::Number => "\x1b[33m",
+            Class::Comment => "\x1b[90m",
+            Class::Operator => "\x1b[36m",
+            Class::Plain => "",
+        }
+    }
+
+    fn css(self) -> &'static str {
+        match self {
+            Class::Keyword => "lox-keyword",
+            Class::Literal => "lox-literal",
+            Class::String => "lox-string",
+            Class::Number => "lox-number",
+            Class::Comment => "lox-comment",
+            Class::Operator => "lox-operator",
+            Class::Plain => "",
+        }
+    }
+}
+
+/// `lox highlight [--format ansi|html] [file]`, reading stdin when no file is given
+pub fn run(args: &[String]) -> i32 {
+    let mut style = Style::Ansi;
+    let mut path = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--format" => {
+                i += 1;
+                style = match args.get(i).map(|s| s.as_str()) {
+                    Some("ansi") => Style::Ansi,
+                    Some("html") => Style::Html,
+                    _ => {
+                        eprintln!("--format expects ansi or html");
+                        return 64;
+                    }
+                };
+            }
+            file => path = Some(file.to_owned()),
+        }
+        i += 1;
+    }
+
+    let source = match &path {
+        Some(p) => fs::read_to_string(p),
+        None => {
+            let mut s = String::new();
+            io::stdin().read_to_string(&mut s).map(|_| s)
+        }
+    };
+    let source = match source {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not read {}: {}", path.unwrap_or("stdin".to_owned()), e);
+            return 1;
+        }
+    };
+
+    // the scanner stops at its first error, which it reports; whatever follows is printed plain
+    let mut scanner = Scanner::new(&source);
+    let mut tokens = Vec::new();
+    let mut failed = false;
+    for token in scanner.by_ref() {
+        match token {
+            Ok(token) => tokens.push(token),
+            Err(_) => failed = true,
+        }
+    }
+    print!("{}", highlight(&source, &tokens, scanner.comments(), style));
+    if failed {
+        return 65;
+    }
+    0
+}
+
+/// colors `source` using the tokens and comments the scanner produced for it
+pub fn highlight(source: &str, tokens: &[RawToken], comments: &[Comment], style: Style) -> String {
+    let mut line_starts = vec![0];
+    for (i, c) in source.bytes().enumerate() {
+        if c == b'\n' {
+            line_starts.push(i + 1);
+        }
+    }
+    let offset = |line: u32, column: u32| {
+        let start = line.checked_sub(1).and_then(|l| line_starts.get(l as usize))?;
+        Some(start + column.checked_sub(1)? as usize)
+    };
+
+    let mut spans = Vec::new();
+    for token in tokens.iter().filter(|t| t.variant != TokenType::EOF) {
+        if let Some(start) = offset(token.line, token.column) {
+            spans.push((start, start + token.lexeme.len(), Class::of(token)));
+        }
+    }
+    for comment in comments.iter() {
+        if let Some(start) = offset(comment.line, comment.column) {
+            spans.push((start, start + comment.text.len(), Class::Comment));
+        }
+    }
+    spans.sort_by_key(|s| s.0);
+
+    let mut out = String::new();
+    if style == Style::Html {
+        out.push_str("<pre class=\"lox\"><code>");
+    }
+    let mut at = 0;
+    for (start, end, class) in spans {
+        if start < at || end > source.len() {
+            continue;
+        }
+        out.push_str(&text(&source[at..start], style));
+        out.push_str(&paint(&source[start..end], class, style));
+        at = end;
+    }
+    out.push_str(&text(&source[at..], style));
+    if style == Style::Html {
+        out.push_str("</code></pre>\n");
+    }
+    out
+}
+
+/// a runtime value as the REPL echoes it
+pub fn value(value: &Value) -> String {
+    let class = match value {
+        Value::S(_) => Class::String,
+        Value::Int(_) => Class::Number,
+        Value::Bool(_) | Value::Nil => Class::Literal,
+        Value::Native(_) => Class::Keyword,
+    };
+    paint(&value.to_string(), class, Style::Ansi)
+}
+
+fn paint(s: &str, class: Class, style: Style) -> String {
+    match style {
+        _ if class == Class::Plain => text(s, style),
+        Style::Ansi => format!("{}{}\x1b[0m", class.ansi(), s),
+        Style::Html => format!("<span class=\"{}\">{}</span>", class.css(), text(s, style)),
+    }
+}
+
+fn text(s: &str, style: Style) -> String {
+    match style {
+        Style::Ansi => s.to_owned(),
+        Style::Html => s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight, Style};
+    use crate::parsing::scanner::Scanner;
+    use crate::parsing::tokens::{RawToken, TokenType};
+
+    fn html(source: &str) -> String {
+        let mut scanner = Scanner::new(source);
+        let tokens: Vec<RawToken> = scanner.by_ref().filter_map(Result::ok).collect();
+        highlight(source, &tokens, scanner.comments(), Style::Html)
+    }
+
+    #[test]
+    fn colors_tokens_and_comments() {
+        assert_eq!(
+            html("print 1; // one"),
+            "<pre class=\"lox\"><code><span class=\"lox-keyword\">print</span> <span class=\"lox-number\">1</span>; <span class=\"lox-comment\">// one</span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_strings_are_colored_from_their_start() {
+        assert_eq!(
+            html("print \"a\nb\";"),
+            "<pre class=\"lox\"><code><span class=\"lox-keyword\">print</span> <span class=\"lox-string\">\"a\nb\"</span>;</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn source_after_a_scan_error_is_plain() {
+        let out = html("print 1;\nprint \"open");
+        assert!(out.contains("<span class=\"lox-number\">1</span>"));
+        assert!(out.ends_with("</span> \"open</code></pre>\n"));
+    }
+
+    #[test]
+    fn positions_before_the_source_are_skipped() {
+        let token = RawToken { variant: TokenType::NIL, lexeme: "nil", line: 0, column: 0 };
+        assert_eq!(highlight("nil", &[token], &[], Style::Ansi), "nil");
+    }
+}
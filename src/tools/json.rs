@@ -0,0 +1,300 @@
+use std::fmt;
+
+/// just enough JSON for the editor protocols
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(input: &str) -> Option<Json> {
+        let mut p = JsonParser { bytes: input.as_bytes(), pos: 0 };
+        let value = p.value()?;
+        p.whitespace();
+        if p.pos != p.bytes.len() {
+            return None;
+        }
+        Some(value)
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+    }
+
+    pub fn str(s: &str) -> Json {
+        Json::Str(s.to_owned())
+    }
+
+    pub fn get(&self, key: &str) -> &Json {
+        if let Json::Object(fields) = self {
+            for (k, v) in fields {
+                if k == key {
+                    return v;
+                }
+            }
+        }
+        &Json::Null
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::Str(s) => write_escaped(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn whitespace(&mut self) {
+        while self.pos < self.bytes.len() && (self.bytes[self.pos] as char).is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: &str) -> Option<()> {
+        if self.bytes[self.pos..].starts_with(expected.as_bytes()) {
+            self.pos += expected.len();
+            return Some(());
+        }
+        None
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match *self.bytes.get(self.pos)? {
+            b'n' => self.eat("null").map(|_| Json::Null),
+            b't' => self.eat("true").map(|_| Json::Bool(true)),
+            b'f' => self.eat("false").map(|_| Json::Bool(false)),
+            b'"' => self.string().map(Json::Str),
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.eat("]").is_some() {
+                    return Some(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.whitespace();
+                    if self.eat(",").is_none() {
+                        self.eat("]")?;
+                        return Some(Json::Array(items));
+                    }
+                }
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.whitespace();
+                if self.eat("}").is_some() {
+                    return Some(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.eat(":")?;
+                    fields.push((key, self.value()?));
+                    self.whitespace();
+                    if self.eat(",").is_none() {
+                        self.eat("}")?;
+                        return Some(Json::Object(fields));
+                    }
+                }
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        text.parse().ok().map(Json::Number)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat("\"")?;
+        let mut out = Vec::new();
+        loop {
+            let b = *self.bytes.get(self.pos)?;
+            self.pos += 1;
+            match b {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.pos)?;
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => self.unicode_escape()?,
+                        other => other as char,
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(b),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Option<char> {
+        let hex = |p: &mut Self| -> Option<u32> {
+            let digits = p.bytes.get(p.pos..p.pos + 4)?;
+            if !digits.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            p.pos += 4;
+            u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+        };
+        let high = hex(self)?;
+        if (0xD800..0xDC00).contains(&high) {
+            // a high surrogate must be followed by a low one
+            self.eat("\\u")?;
+            let low = hex(self)?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                return None;
+            }
+            return char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00));
+        }
+        // a lone low surrogate is not a char, so this fails too
+        char::from_u32(high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+
+    #[test]
+    fn parses_nested_values() {
+        let parsed = Json::parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "d"}} "#).unwrap();
+        assert_eq!(parsed.get("a").as_array()[1], Json::Number(-25.0));
+        assert_eq!(parsed.get("a").as_array()[2].as_bool(), Some(true));
+        assert!(parsed.get("a").as_array()[3].is_null());
+        assert_eq!(parsed.get("b").get("c").as_str(), Some("d"));
+        assert!(parsed.get("missing").is_null());
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        let value = Json::object(vec![
+            ("s", Json::str("quote \" slash \\ newline \n tab \t bell \u{7}")),
+            ("n", Json::Number(3.0)),
+            ("f", Json::Number(0.5)),
+            ("a", Json::Array(vec![Json::Null, Json::Bool(false)])),
+        ]);
+        assert_eq!(Json::parse(&value.to_string()), Some(value));
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(Json::parse(r#""a\u0041\n\/""#), Some(Json::str("aA\n/")));
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#), Some(Json::str("\u{1F600}")));
+    }
+
+    #[test]
+    fn rejects_broken_surrogates() {
+        assert_eq!(Json::parse(r#""\ud800\u0041""#), None);
+        assert_eq!(Json::parse(r#""\ud800x""#), None);
+        assert_eq!(Json::parse(r#""\udc00""#), None);
+        assert_eq!(Json::parse(r#""\u12g4""#), None);
+        assert_eq!(Json::parse(r#""\u+123""#), None);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for text in ["", "{", "[1,]", r#"{"a" 1}"#, r#""open"#, "nul", "1 2", "{} x"] {
+            assert_eq!(Json::parse(text), None, "{}", text);
+        }
+    }
+}
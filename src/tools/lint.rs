@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::parsing::expressions::{Binary, Call, Expr, ExprId, Grouping, Literal, Unary, Variable};
+use crate::parsing::checker;
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::ast::{Ast, ExprVisitor, Program, StmtVisitor};
+use crate::parsing::statements::{Dec, ExprStmt, Print, Test};
+use crate::parsing::tokens::{Token, TokenType};
+
+/// every lint the checker knows about, as (id, description).
+/// unused-parameter, unreachable-code, constant-condition and self-assignment wait on
+/// functions, `return`, `if` and assignment, which the language doesn't have yet
+pub const LINTS: [(&str, &str); 3] = [
+    ("unused-variable", "a variable is declared but never read"),
+    ("shadowed-variable", "a declaration in a test block hides an outer one with the same name"),
+    ("self-comparison", "an expression is compared against itself"),
+];
+
+#[derive(Debug)]
+pub struct Warning {
+    pub id: &'static str,
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+/// `lox lint [--allow <id>]... [--list] files`
+pub fn run(args: &[String]) -> i32 {
+    let mut allowed = Vec::new();
+    let mut paths = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--list" => {
+                for (id, description) in LINTS.iter() {
+                    println!("{:<20} {}", id, description);
+                }
+                return 0;
+            }
+            "--allow" | "-A" => {
+                i += 1;
+                match args.get(i) {
+                    Some(id) if LINTS.iter().any(|(l, _)| l == id) => allowed.push(id.clone()),
+                    Some(id) => {
+                        eprintln!("unknown lint '{}'", id);
+                        return 1;
+                    }
+                    None => {
+                        eprintln!("--allow expects a lint id");
+                        return 1;
+                    }
+                }
+            }
+            _ => paths.push(&args[i]),
+        }
+        i += 1;
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("could not read {}: {}", path, e);
+                code = 1;
+                continue;
+            }
+        };
+        let program = match Parser::new(Scanner::new(&source)).parse().filter(checker::report) {
+            Some(program) => program,
+            None => {
+                code = 1;
+                continue;
+            }
+        };
+        for w in lint(&program, &allowed) {
+            println!("{}:{}:{}: warning[{}]: {}", path, w.line, w.column, w.id, w.message);
+            code = 1;
+        }
+    }
+    code
+}
+
+/// runs every lint not in `allowed` over the statements, sorted by location
+pub fn lint(program: &Program, allowed: &[String]) -> Vec<Warning> {
+    let mut linter = Linter::new();
+    for s in program.statements.iter() {
+        program.ast.visit_stmt(*s, &mut linter);
+    }
+    linter.finish();
+
+    let mut warnings: Vec<Warning> = linter
+        .warnings
+        .into_iter()
+        .filter(|w| !allowed.iter().any(|a| a == w.id))
+        .collect();
+    warnings.sort_by_key(|w| (w.line, w.column));
+    warnings
+}
+
+struct Declaration {
+    name: Token,
+    used: bool,
+    // how many test blocks enclose it; 0 is a global
+    depth: usize,
+}
+
+struct Linter {
+    declarations: Vec<Declaration>,
+    // name -> index of the declaration currently in effect
+    current: HashMap<String, usize>,
+    warnings: Vec<Warning>,
+    // how many test blocks we are inside
+    depth: usize,
+}
+
+impl Linter {
+    fn new() -> Self {
+        Linter {
+            declarations: Vec::new(),
+            current: HashMap::new(),
+            warnings: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn warn(&mut self, id: &'static str, at: &Token, message: String) {
+        self.warnings.push(Warning { id, line: at.line, column: at.column, message });
+    }
+
+    fn finish(&mut self) {
+        let unused: Vec<Token> = self
+            .declarations
+            .iter()
+            // globals can still be read by later REPL input or a test block, so only locals count as unused
+            .filter(|d| d.depth > 0 && !d.used)
+            .map(|d| d.name.clone())
+            .collect();
+        for name in unused {
+            self.warn("unused-variable", &name, format!("variable '{}' is never read", name.lexeme));
+        }
+    }
+}
+
+/// true when `a` and `b` are the same expression, and evaluating either has no side effects
+fn same(ast: &Ast, a: ExprId, b: ExprId) -> bool {
+    match (ast.expr(a), ast.expr(b)) {
+        (Expr::Grouping(g), _) => same(ast, g.expr, b),
+        (_, Expr::Grouping(g)) => same(ast, a, g.expr),
+        (Expr::Literal(x), Expr::Literal(y)) => x.val == y.val,
+        (Expr::Variable(x), Expr::Variable(y)) => x.symbol == y.symbol,
+        (Expr::Unary(x), Expr::Unary(y)) => x.operator.variant == y.operator.variant && same(ast, x.right, y.right),
+        (Expr::Binary(x), Expr::Binary(y)) => {
+            x.operator.variant == y.operator.variant && same(ast, x.left, y.left) && same(ast, x.right, y.right)
+        }
+        // a call may do something different each time
+        _ => false,
+    }
+}
+
+/// true when `e` is built from literals alone, so comparing it with itself is deliberate
+fn is_constant(ast: &Ast, e: ExprId) -> bool {
+    match ast.expr(e) {
+        Expr::Literal(_) => true,
+        Expr::Grouping(g) => is_constant(ast, g.expr),
+        Expr::Unary(u) => is_constant(ast, u.right),
+        Expr::Binary(b) => is_constant(ast, b.left) && is_constant(ast, b.right),
+        Expr::Variable(_) | Expr::Call(_) => false,
+    }
+}
+
+impl ExprVisitor for Linter {
+    type Output = ();
+
+    fn visit_binary(&mut self, ast: &Ast, b: &Binary) {
+        let compares = matches!(
+            b.operator.variant,
+            TokenType::EQUAL_EQUAL
+                | TokenType::BANG_EQUAL
+                | TokenType::GREATER
+                | TokenType::GREATER_EQUAL
+                | TokenType::LESS
+                | TokenType::LESS_EQUAL
+        );
+        if compares && !is_constant(ast, b.left) && same(ast, b.left, b.right) {
+            self.warn(
+                "self-comparison",
+                &b.operator,
+                format!("both sides of '{}' are the same expression", b.operator.lexeme),
+            );
+        }
+        ast.visit_expr(b.left, self);
+        ast.visit_expr(b.right, self);
+    }
+    fn visit_grouping(&mut self, ast: &Ast, g: &Grouping) {
+        ast.visit_expr(g.expr, self);
+    }
+    fn visit_literal(&mut self, _ast: &Ast, _l: &Literal) {}
+    fn visit_unary(&mut self, ast: &Ast, u: &Unary) {
+        ast.visit_expr(u.right, self);
+    }
+    fn visit_variable(&mut self, _ast: &Ast, v: &Variable) {
+        if let Some(i) = self.current.get(&v.name.lexeme) {
+            self.declarations[*i].used = true;
+        }
+    }
+    fn visit_call(&mut self, ast: &Ast, c: &Call) {
+        ast.visit_expr(c.callee, self);
+        for a in c.arguments.iter() {
+            ast.visit_expr(*a, self);
+        }
+    }
+}
+
+impl StmtVisitor for Linter {
+    type Output = ();
+
+    fn visit_expr_stmt(&mut self, ast: &Ast, s: &ExprStmt) {
+        ast.visit_expr(s.expr, self);
+    }
+    fn visit_print_stmt(&mut self, ast: &Ast, s: &Print) {
+        ast.visit_expr(s.expr, self);
+    }
+    fn visit_declaration(&mut self, ast: &Ast, d: &Dec) {
+        // the initializer sees the previous declaration, if any
+        ast.visit_expr(d.expr, self);
+
+        // only an inner block can hide a name; a second `var` in the same scope is a redeclaration
+        let hidden = self.current.get(&d.name.lexeme).map(|i| &self.declarations[*i]).filter(|outer| outer.depth < self.depth);
+        if let Some(line) = hidden.map(|outer| outer.name.line) {
+            self.warn(
+                "shadowed-variable",
+                &d.name,
+                format!("'{}' shadows the declaration on line {}", d.name.lexeme, line),
+            );
+        }
+        self.declarations.push(Declaration { name: d.name.clone(), used: false, depth: self.depth });
+        self.current.insert(d.name.lexeme.clone(), self.declarations.len() - 1);
+    }
+    fn visit_test(&mut self, ast: &Ast, t: &Test) {
+        // names declared in the body go out of scope at its end
+        let outer = self.current.clone();
+        self.depth += 1;
+        for s in t.body.iter() {
+            ast.visit_stmt(*s, self);
+        }
+        self.depth -= 1;
+        self.current = outer;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsing::parser::Parser;
+    use crate::parsing::scanner::Scanner;
+
+    use super::lint;
+
+    fn ids(source: &str) -> Vec<&'static str> {
+        let program = Parser::new(Scanner::new(source)).parse().unwrap();
+        lint(&program, &[]).iter().map(|w| w.id).collect()
+    }
+
+    #[test]
+    fn unused_locals_are_flagged() {
+        assert_eq!(ids("test \"t\" { var a = 1; }"), vec!["unused-variable"]);
+        assert!(ids("test \"t\" { var a = 1; print a; }").is_empty());
+    }
+
+    #[test]
+    fn unused_globals_are_not_flagged() {
+        assert!(ids("var a = 1;").is_empty());
+    }
+
+    #[test]
+    fn locals_go_out_of_scope_after_their_block() {
+        // the global read after the test must not count as a use of the local
+        assert_eq!(ids("var a = 1;\ntest \"t\" { var a = 2; }\nprint a;"), vec!["shadowed-variable", "unused-variable"]);
+    }
+
+    #[test]
+    fn shadowing_is_flagged() {
+        assert_eq!(ids("var a = 1;\ntest \"t\" { var a = 2; print a; }"), vec!["shadowed-variable"]);
+        assert_eq!(ids("test \"t\" { var a = 1; test \"u\" { var a = 2; print a; } print a; }"), vec!["shadowed-variable"]);
+        assert!(ids("var a = 1;\nvar b = a;").is_empty());
+    }
+
+    #[test]
+    fn redeclaring_a_global_is_not_shadowing() {
+        assert!(ids("var x = 1;\nvar x = 2;").is_empty());
+        assert!(ids("var a = 1;\nvar a = a;").is_empty());
+    }
+
+    #[test]
+    fn comparing_an_expression_with_itself_is_flagged() {
+        assert_eq!(ids("var a = 1;\nprint a == a;"), vec!["self-comparison"]);
+        assert_eq!(ids("var a = 1;\nprint (a + 1) >= a + 1;"), vec!["self-comparison"]);
+        assert_eq!(ids("var a = 1;\nprint -a != (-a);"), vec!["self-comparison"]);
+    }
+
+    #[test]
+    fn self_comparison_skips_literals_calls_and_different_operands() {
+        assert!(ids("print 1 == 1;").is_empty());
+        assert!(ids("print gc() == gc();").is_empty());
+        assert!(ids("var a = 1;\nvar b = 2;\nprint a == b;").is_empty());
+        assert!(ids("var a = 1;\nprint a + 1 == a + 2;").is_empty());
+        assert!(ids("var a = 1;\nprint a - 1 == 1 - a;").is_empty());
+    }
+
+    #[test]
+    fn allowed_lints_are_dropped() {
+        let program = Parser::new(Scanner::new("test \"t\" { var a = 1; }")).parse().unwrap();
+        assert!(lint(&program, &["unused-variable".to_owned()]).is_empty());
+    }
+}
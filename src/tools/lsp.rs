@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::errors::err::SyntaxErr;
+use crate::parsing::keywords::keywords_map;
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::tokens::{RawToken, Token, TokenType};
+
+use super::json::Json;
+use super::lint::{lint, Warning};
+use super::rpc::{read_frame, write_message, Frame};
+
+// LSP enum values
+const SEVERITY_ERROR: f64 = 1.0;
+const SEVERITY_WARNING: f64 = 2.0;
+const SYMBOL_CLASS: f64 = 5.0;
+const SYMBOL_FUNCTION: f64 = 12.0;
+const SYMBOL_VARIABLE: f64 = 13.0;
+const COMPLETION_FUNCTION: f64 = 3.0;
+const COMPLETION_VARIABLE: f64 = 6.0;
+const COMPLETION_CLASS: f64 = 7.0;
+const COMPLETION_KEYWORD: f64 = 14.0;
+const PARSE_ERROR: f64 = -32700.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+
+/// `lox lsp`, a language server speaking JSON-RPC over stdin/stdout
+pub fn run() -> i32 {
+    serve(&mut io::stdin().lock(), io::stdout().lock())
+}
+
+fn serve(input: &mut impl BufRead, output: impl Write) -> i32 {
+    let mut server = Server::new(output);
+    while let Some(frame) = read_frame(input) {
+        let message = match frame {
+            Frame::Message(m) => m,
+            Frame::Malformed => {
+                server.error(&Json::Null, PARSE_ERROR, "Parse error");
+                continue;
+            }
+        };
+        if let Some(code) = server.handle(&message) {
+            return code;
+        }
+    }
+    1
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn new(output: W) -> Self {
+        Server { output, documents: HashMap::new(), shutdown: false }
+    }
+
+    /// returns the exit code once the client asks us to exit
+    fn handle(&mut self, message: &Json) -> Option<i32> {
+        let id = message.get("id");
+        let params = message.get("params");
+        let method = message.get("method").as_str().unwrap_or("");
+
+        let result = match method {
+            "initialize" => Json::object(vec![("capabilities", capabilities())]),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "exit" => return Some(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let doc = params.get("textDocument");
+                self.open(doc.get("uri"), doc.get("text"));
+                return None;
+            }
+            "textDocument/didChange" => {
+                // full sync, so the last change holds the whole text
+                let text = params.get("contentChanges").as_array().last().map(|c| c.get("text").clone());
+                self.open(params.get("textDocument").get("uri"), &text.unwrap_or(Json::Null));
+                return None;
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params.get("textDocument").get("uri").as_str() {
+                    self.documents.remove(uri);
+                    self.publish(uri, Vec::new());
+                }
+                return None;
+            }
+            "textDocument/hover" => self.with_symbol(params, |doc, s| doc.hover(s)),
+            "textDocument/definition" => {
+                let uri = params.get("textDocument").get("uri").clone();
+                self.with_symbol(params, |doc, s| match s.declaration {
+                    Some(d) => doc.location(&uri, &doc.declarations[d].token),
+                    None => Json::Null,
+                })
+            }
+            "textDocument/references" => {
+                let uri = params.get("textDocument").get("uri").clone();
+                let include_declaration = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                self.with_symbol(params, |doc, s| {
+                    Json::Array(
+                        doc.references_of(s, include_declaration)
+                            .iter()
+                            .map(|r| doc.location(&uri, &r.token))
+                            .collect(),
+                    )
+                })
+            }
+            "textDocument/documentSymbol" => match self.document(params) {
+                Some(doc) => doc.document_symbols(),
+                None => Json::Array(Vec::new()),
+            },
+            "textDocument/completion" => match self.document(params) {
+                Some(doc) => doc.completions(),
+                None => Json::Array(Vec::new()),
+            },
+            _ => {
+                if !id.is_null() {
+                    self.error(id, METHOD_NOT_FOUND, &format!("unhandled method {}", method));
+                }
+                return None;
+            }
+        };
+
+        self.send(Json::object(vec![("jsonrpc", Json::str("2.0")), ("id", id.clone()), ("result", result)]));
+        None
+    }
+
+    fn open(&mut self, uri: &Json, text: &Json) {
+        let (uri, text) = match (uri.as_str(), text.as_str()) {
+            (Some(u), Some(t)) => (u.to_owned(), t.to_owned()),
+            _ => return,
+        };
+        let doc = Document::new(text);
+        let diagnostics = doc.diagnostics();
+        self.documents.insert(uri.clone(), doc);
+        self.publish(&uri, diagnostics);
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) {
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::str("2.0")),
+            ("method", Json::str("textDocument/publishDiagnostics")),
+            ("params", Json::object(vec![("uri", Json::str(uri)), ("diagnostics", Json::Array(diagnostics))])),
+        ]));
+    }
+
+    fn document(&self, params: &Json) -> Option<&Document> {
+        self.documents.get(params.get("textDocument").get("uri").as_str()?)
+    }
+
+    // runs `f` on the symbol under the cursor, answering null when there is none
+    fn with_symbol(&self, params: &Json, f: impl Fn(&Document, &Symbol) -> Json) -> Json {
+        let doc = match self.document(params) {
+            Some(doc) => doc,
+            None => return Json::Null,
+        };
+        let position = params.get("position");
+        let line = position.get("line").as_i64().unwrap_or(-1);
+        let character = position.get("character").as_i64().unwrap_or(-1);
+        match doc.symbol_at(line, character) {
+            Some(s) => f(doc, s),
+            None => Json::Null,
+        }
+    }
+
+    fn error(&mut self, id: &Json, code: f64, message: &str) {
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::str("2.0")),
+            ("id", id.clone()),
+            ("error", Json::object(vec![("code", Json::Number(code)), ("message", Json::str(message))])),
+        ]));
+    }
+
+    fn send(&mut self, message: Json) {
+        if let Err(e) = write_message(&mut self.output, &message) {
+            eprintln!("could not write to client: {}", e);
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        ("textDocumentSync", Json::Number(1.0)),
+        ("hoverProvider", Json::Bool(true)),
+        ("definitionProvider", Json::Bool(true)),
+        ("referencesProvider", Json::Bool(true)),
+        ("documentSymbolProvider", Json::Bool(true)),
+        ("completionProvider", Json::object(vec![])),
+    ])
+}
+
+/// an identifier occurrence, tied to the declaration it refers to
+struct Symbol {
+    token: Token,
+    declaration: Option<usize>,
+}
+
+struct Declaration {
+    token: Token,
+    kind: TokenType,
+}
+
+struct Document {
+    text: String,
+    // byte offset where each line starts
+    line_starts: Vec<usize>,
+    errors: Vec<SyntaxErr>,
+    warnings: Vec<Warning>,
+    declarations: Vec<Declaration>,
+    symbols: Vec<Symbol>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        let mut doc = Document {
+            text,
+            line_starts,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            declarations: Vec::new(),
+            symbols: Vec::new(),
+        };
+
+        // the parser reports a scan error too, so only the tokens before it are kept here
+        let tokens: Vec<RawToken> = Scanner::new(&doc.text).map_while(Result::ok).collect();
+
+        let mut parser = Parser::new(Scanner::new(&doc.text));
+        match parser.parse() {
+            Some(program) => doc.warnings = lint(&program, &[]),
+            None => doc.errors = parser.errors().to_vec(),
+        }
+
+        // symbols come from the token stream so they still work while the file doesn't parse or scan
+        for (i, token) in tokens.iter().enumerate() {
+            if token.variant != TokenType::IDENTIFIER {
+                continue;
+            }
+            let introducer = if i > 0 { tokens[i - 1].variant } else { TokenType::EOF };
+            if matches!(introducer, TokenType::VAR | TokenType::DEF | TokenType::CLASS) {
+                doc.declarations.push(Declaration { token: token.to_token(), kind: introducer });
+            }
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            if token.variant != TokenType::IDENTIFIER {
+                continue;
+            }
+            let declaration = doc.resolve(&tokens, i);
+            doc.symbols.push(Symbol { token: token.to_token(), declaration });
+        }
+        doc
+    }
+
+    // the latest declaration of the name at or before this token, else the first one after it
+    fn resolve(&self, tokens: &[RawToken], i: usize) -> Option<usize> {
+        let name = tokens[i].lexeme;
+        let at = (tokens[i].line, tokens[i].column);
+        let mut found = None;
+        for (d, decl) in self.declarations.iter().enumerate() {
+            if decl.token.lexeme != *name {
+                continue;
+            }
+            if (decl.token.line, decl.token.column) <= at || found.is_none() {
+                found = Some(d);
+            }
+            if (decl.token.line, decl.token.column) > at {
+                break;
+            }
+        }
+        found
+    }
+
+    // the text of a 1-based line, without its newline
+    fn line(&self, line: u32) -> &str {
+        let start = match line.checked_sub(1).and_then(|l| self.line_starts.get(l as usize)) {
+            Some(s) => *s,
+            None => return "",
+        };
+        let rest = &self.text[start..];
+        rest.split('\n').next().unwrap_or("")
+    }
+
+    /// LSP counts characters in UTF-16 code units; the scanner counts 1-based byte columns
+    fn character(&self, line: u32, column: usize) -> usize {
+        let end = column.saturating_sub(1);
+        self.line(line).char_indices().take_while(|(i, _)| *i < end).map(|(_, c)| c.len_utf16()).sum()
+    }
+
+    /// the 1-based byte column of a UTF-16 `character` offset
+    fn column(&self, line: u32, character: usize) -> usize {
+        let text = self.line(line);
+        let mut units = 0;
+        for (i, c) in text.char_indices() {
+            if units >= character {
+                return i + 1;
+            }
+            units += c.len_utf16();
+        }
+        text.len() + 1
+    }
+
+    fn range(&self, line: u32, column: u32, length: usize) -> Json {
+        let position = |column: usize| {
+            Json::object(vec![
+                ("line", Json::Number(line.saturating_sub(1) as f64)),
+                ("character", Json::Number(self.character(line, column) as f64)),
+            ])
+        };
+        let start = column as usize;
+        Json::object(vec![("start", position(start)), ("end", position(start + length))])
+    }
+
+    fn token_range(&self, token: &Token) -> Json {
+        self.range(token.line, token.column, token.lexeme.len())
+    }
+
+    fn location(&self, uri: &Json, token: &Token) -> Json {
+        Json::object(vec![("uri", uri.clone()), ("range", self.token_range(token))])
+    }
+
+    fn diagnostics(&self) -> Vec<Json> {
+        let mut out: Vec<Json> = self
+            .errors
+            .iter()
+            .map(|e| {
+                Json::object(vec![
+                    ("range", self.range(e.line, e.column, 1)),
+                    ("severity", Json::Number(SEVERITY_ERROR)),
+                    ("source", Json::str("lox")),
+                    ("message", Json::str(&e.message)),
+                ])
+            })
+            .collect();
+        for w in self.warnings.iter() {
+            out.push(Json::object(vec![
+                ("range", self.range(w.line, w.column, 1)),
+                ("severity", Json::Number(SEVERITY_WARNING)),
+                ("source", Json::str("lox lint")),
+                ("code", Json::str(w.id)),
+                ("message", Json::str(&w.message)),
+            ]));
+        }
+        out
+    }
+
+    fn symbol_at(&self, line: i64, character: i64) -> Option<&Symbol> {
+        let line = u32::try_from(line).ok()?.checked_add(1)?;
+        let column = self.column(line, usize::try_from(character).ok()?);
+        self.symbols.iter().find(|s| {
+            let start = s.token.column as usize;
+            s.token.line == line && start <= column && column <= start + s.token.lexeme.len()
+        })
+    }
+
+    // undeclared names (e.g. a typo) are grouped by name so they can still be found
+    fn references_of(&self, s: &Symbol, include_declaration: bool) -> Vec<&Symbol> {
+        let declared_at = s.declaration.map(|d| {
+            let t = &self.declarations[d].token;
+            (t.line, t.column)
+        });
+        self.symbols
+            .iter()
+            .filter(|r| r.declaration == s.declaration && r.token.lexeme == s.token.lexeme)
+            .filter(|r| include_declaration || Some((r.token.line, r.token.column)) != declared_at)
+            .collect()
+    }
+
+    fn hover(&self, s: &Symbol) -> Json {
+        let declaration = match s.declaration {
+            Some(d) => &self.declarations[d],
+            None => return Json::Null,
+        };
+        let line = declaration.token.line;
+        let source = self.line(line).trim();
+        Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![
+                    ("kind", Json::str("markdown")),
+                    ("value", Json::Str(format!("```lox\n{}\n```\ndeclared on line {}", source, line))),
+                ]),
+            ),
+            ("range", self.token_range(&s.token)),
+        ])
+    }
+
+    fn document_symbols(&self) -> Json {
+        Json::Array(
+            self.declarations
+                .iter()
+                .map(|d| {
+                    let kind = match d.kind {
+                        TokenType::DEF => SYMBOL_FUNCTION,
+                        TokenType::CLASS => SYMBOL_CLASS,
+                        _ => SYMBOL_VARIABLE,
+                    };
+                    Json::object(vec![
+                        ("name", Json::str(&d.token.lexeme)),
+                        ("kind", Json::Number(kind)),
+                        ("range", self.token_range(&d.token)),
+                        ("selectionRange", self.token_range(&d.token)),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn completions(&self) -> Json {
+        let mut keywords: Vec<String> = keywords_map().into_keys().collect();
+        keywords.sort();
+        let mut items: Vec<Json> = keywords
+            .iter()
+            .map(|k| Json::object(vec![("label", Json::str(k)), ("kind", Json::Number(COMPLETION_KEYWORD))]))
+            .collect();
+
+        let mut seen = Vec::new();
+        for d in self.declarations.iter() {
+            if seen.contains(&&d.token.lexeme) {
+                continue;
+            }
+            seen.push(&d.token.lexeme);
+            let kind = match d.kind {
+                TokenType::DEF => COMPLETION_FUNCTION,
+                TokenType::CLASS => COMPLETION_CLASS,
+                _ => COMPLETION_VARIABLE,
+            };
+            items.push(Json::object(vec![("label", Json::str(&d.token.lexeme)), ("kind", Json::Number(kind))]));
+        }
+        Json::Array(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::json::Json;
+    use super::super::rpc::{read_frame, Frame};
+    use super::serve;
+
+    fn request(id: i64, method: &str, params: Json) -> String {
+        let message = Json::object(vec![
+            ("jsonrpc", Json::str("2.0")),
+            ("id", Json::Number(id as f64)),
+            ("method", Json::str(method)),
+            ("params", params),
+        ]);
+        frame(&message.to_string())
+    }
+
+    fn notify(method: &str, params: Json) -> String {
+        let message = Json::object(vec![("jsonrpc", Json::str("2.0")), ("method", Json::str(method)), ("params", params)]);
+        frame(&message.to_string())
+    }
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn open(text: &str) -> String {
+        let document = Json::object(vec![("uri", Json::str("file:///a.lox")), ("text", Json::str(text))]);
+        notify("textDocument/didOpen", Json::object(vec![("textDocument", document)]))
+    }
+
+    fn at(line: i64, character: i64) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::str("file:///a.lox"))])),
+            ("position", Json::object(vec![("line", Json::Number(line as f64)), ("character", Json::Number(character as f64))])),
+        ])
+    }
+
+    // runs a whole session, ending with shutdown and exit, and returns every message the server sent
+    fn session(messages: &[String]) -> Vec<Json> {
+        let mut input = messages.concat();
+        input.push_str(&request(0, "shutdown", Json::Null));
+        input.push_str(&notify("exit", Json::Null));
+        let mut output = Vec::new();
+        assert_eq!(serve(&mut input.as_bytes(), &mut output), 0);
+
+        let mut read = output.as_slice();
+        let mut sent = Vec::new();
+        while let Some(frame) = read_frame(&mut read) {
+            match frame {
+                Frame::Message(m) => sent.push(m),
+                Frame::Malformed => panic!("server sent a malformed message"),
+            }
+        }
+        sent
+    }
+
+    fn start(range: &Json) -> (i64, i64) {
+        let start = range.get("start");
+        (start.get("line").as_i64().unwrap(), start.get("character").as_i64().unwrap())
+    }
+
+    #[test]
+    fn malformed_frames_get_a_parse_error_and_the_server_keeps_going() {
+        let sent = session(&[frame("{not json"), request(1, "initialize", Json::Null)]);
+        let error = sent[0].get("error");
+        assert_eq!(error.get("code").as_i64(), Some(-32700));
+        assert!(sent[0].get("id").is_null());
+        assert_eq!(sent[1].get("id").as_i64(), Some(1));
+        assert!(!sent[1].get("result").get("capabilities").is_null());
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        // the emoji is four bytes but two UTF-16 code units, so `s` after it sits at character 20
+        let sent = session(&[
+            open("var s = \"\u{1F600}\"; print s;"),
+            request(1, "textDocument/definition", at(0, 20)),
+            request(2, "textDocument/hover", at(0, 20)),
+            request(3, "textDocument/definition", at(0, 22)),
+        ]);
+        let definition = sent.iter().find(|m| m.get("id").as_i64() == Some(1)).unwrap();
+        assert_eq!(start(definition.get("result").get("range")), (0, 4));
+        let hover = sent.iter().find(|m| m.get("id").as_i64() == Some(2)).unwrap();
+        let range = hover.get("result").get("range");
+        assert_eq!(start(range), (0, 20));
+        assert_eq!(range.get("end").get("character").as_i64(), Some(21));
+        let past_the_end = sent.iter().find(|m| m.get("id").as_i64() == Some(3)).unwrap();
+        assert!(past_the_end.get("result").is_null());
+    }
+
+    #[test]
+    fn diagnostics_count_utf16_code_units() {
+        let sent = session(&[open("var s = \"\u{e9}\"; print s +;")]);
+        let diagnostics = sent[0].get("params").get("diagnostics").as_array();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("message").as_str(), Some("Expect expression."));
+        assert_eq!(start(diagnostics[0].get("range")), (0, 22));
+    }
+
+    #[test]
+    fn symbols_before_a_scan_error_are_listed() {
+        let document = Json::object(vec![("textDocument", Json::object(vec![("uri", Json::str("file:///a.lox"))]))]);
+        let sent = session(&[open("var a = 1;\nvar b = a;\nprint \"open"), request(1, "textDocument/documentSymbol", document)]);
+        let diagnostics = sent[0].get("params").get("diagnostics").as_array();
+        assert_eq!(diagnostics[0].get("message").as_str(), Some("Unterminated string."));
+        let symbols = sent.iter().find(|m| m.get("id").as_i64() == Some(1)).unwrap().get("result").as_array();
+        let names: Vec<&str> = symbols.iter().map(|s| s.get("name").as_str().unwrap()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn unknown_requests_get_method_not_found() {
+        let sent = session(&[request(1, "workspace/symbol", Json::Null)]);
+        assert_eq!(sent[0].get("error").get("code").as_i64(), Some(-32601));
+    }
+}
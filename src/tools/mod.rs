@@ -0,0 +1,29 @@
+pub mod compile;
+pub mod conform;
+pub mod dap;
+pub mod debug;
+pub mod disasm;
+pub mod doc;
+pub mod fmt;
+pub mod highlight;
+pub mod json;
+pub mod lint;
+pub mod lsp;
+pub mod rpc;
+pub mod test;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `path` itself if it is a .lox file, or every .lox file below it
+pub fn collect_lox_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                collect_lox_files(&entry.path(), files);
+            }
+        }
+    } else if path.extension().is_some_and(|e| e == "lox") {
+        files.push(path.to_path_buf());
+    }
+}
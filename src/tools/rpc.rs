@@ -0,0 +1,100 @@
+use std::io::{self, BufRead, Read, Write};
+
+use super::json::Json;
+
+/// the largest body read into memory; anything longer is skipped and reported as malformed
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// one `Content-Length` framed message off the wire
+pub enum Frame {
+    Message(Json),
+    // the frame was read, but its header or body made no sense
+    Malformed,
+}
+
+/// reads one frame, as used by both LSP and DAP. None only at end of input
+pub fn read_frame(input: &mut impl BufRead) -> Option<Frame> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = match length {
+        Some(l) => l,
+        None => return Some(Frame::Malformed),
+    };
+    if length > MAX_BODY {
+        // skip the body without holding it, so the next frame is still found
+        let skipped = io::copy(&mut (&mut *input).take(length as u64), &mut io::sink()).ok()?;
+        return if skipped == length as u64 { Some(Frame::Malformed) } else { None };
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    let message = std::str::from_utf8(&body).ok().and_then(Json::parse);
+    Some(message.map_or(Frame::Malformed, Frame::Message))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_frame, Frame, Json, MAX_BODY};
+
+    fn frames(input: &str) -> Vec<Option<Json>> {
+        let mut input = input.as_bytes();
+        let mut out = Vec::new();
+        while let Some(frame) = read_frame(&mut input) {
+            out.push(match frame {
+                Frame::Message(m) => Some(m),
+                Frame::Malformed => None,
+            });
+        }
+        out
+    }
+
+    #[test]
+    fn reads_consecutive_frames() {
+        let read = frames("Content-Length: 4\r\n\r\nnullContent-Length: 2\r\n\r\n[]");
+        assert_eq!(read, vec![Some(Json::Null), Some(Json::Array(Vec::new()))]);
+    }
+
+    #[test]
+    fn bad_bodies_are_malformed_and_reading_goes_on() {
+        let read = frames("Content-Length: 5\r\n\r\n{bad}Content-Length: 4\r\n\r\ntrue");
+        assert_eq!(read, vec![None, Some(Json::Bool(true))]);
+    }
+
+    #[test]
+    fn a_missing_length_is_malformed() {
+        assert_eq!(frames("Content-Type: x\r\n\r\n"), vec![None]);
+    }
+
+    #[test]
+    fn oversized_bodies_are_malformed_and_skipped() {
+        let body = " ".repeat(MAX_BODY + 1);
+        let input = format!("Content-Length: {}\r\n\r\n{}Content-Length: 4\r\n\r\nnull", body.len(), body);
+        assert_eq!(frames(&input), vec![None, Some(Json::Null)]);
+        assert!(frames("Content-Length: 99999999999\r\n\r\nnull").is_empty());
+    }
+
+    #[test]
+    fn a_truncated_body_ends_the_input() {
+        assert!(frames("Content-Length: 10\r\n\r\nnull").is_empty());
+    }
+}
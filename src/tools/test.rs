@@ -0,0 +1,337 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::parsing::checker;
+use crate::parsing::parser::Parser;
+use crate::parsing::scanner::Scanner;
+use crate::parsing::ast::Program;
+use crate::parsing::statements::Stmt;
+use crate::runtime::interpreter::Interpreter;
+
+#[derive(PartialEq)]
+enum Format {
+    Human,
+    Tap,
+    Junit,
+}
+
+struct TestResult {
+    file: String,
+    name: String,
+    // None when the test passed
+    failure: Option<String>,
+    output: String,
+    time: Duration,
+}
+
+/// `lox test [--format human|tap|junit] [paths]`, runs every `test` block under the paths.
+/// Only `test "name" { ... }` blocks are tests, there is no `test_` naming convention
+pub fn run(args: &[String]) -> i32 {
+    let (format, paths) = match options(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 64;
+        }
+    };
+
+    let mut results = Vec::new();
+    for path in paths.iter() {
+        if !path.exists() {
+            eprintln!("{} does not exist", path.display());
+            return 64;
+        }
+        let mut files = Vec::new();
+        super::collect_lox_files(path, &mut files);
+        files.sort();
+
+        let before = results.len();
+        for file in files.iter() {
+            run_file(file, &mut results);
+        }
+        if results.len() == before {
+            eprintln!("no tests found in {}", path.display());
+            return 1;
+        }
+    }
+
+    match format {
+        Format::Human => print_human(&results),
+        Format::Tap => print_tap(&results),
+        Format::Junit => print_junit(&results),
+    }
+    if results.iter().any(|r| r.failure.is_some()) {
+        return 1;
+    }
+    0
+}
+
+fn options(args: &[String]) -> Result<(Format, Vec<PathBuf>), String> {
+    let mut format = Format::Human;
+    let mut paths = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--format" => {
+                i += 1;
+                format = match args.get(i).map(|s| s.as_str()) {
+                    Some("human") => Format::Human,
+                    Some("tap") => Format::Tap,
+                    Some("junit") => Format::Junit,
+                    _ => return Err("--format expects human, tap or junit".to_owned()),
+                };
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            path => paths.push(PathBuf::from(path)),
+        }
+        i += 1;
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    Ok((format, paths))
+}
+
+fn parse(source: &str) -> Option<Program> {
+    Parser::new(Scanner::new(source)).parse().filter(checker::report)
+}
+
+// every test gets a fresh interpreter that runs the file's top level plus that one test
+fn run_file(path: &Path, results: &mut Vec<TestResult>) {
+    let file = path.display().to_string();
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            results.push(TestResult {
+                file: file.clone(),
+                name: "<load>".to_owned(),
+                failure: Some(format!("could not read {}: {}", file, e)),
+                output: String::new(),
+                time: Duration::ZERO,
+            });
+            return;
+        }
+    };
+    let program = match parse(&source) {
+        Some(program) => program,
+        None => {
+            results.push(TestResult {
+                file,
+                name: "<parse>".to_owned(),
+                failure: Some("the file has syntax errors".to_owned()),
+                output: String::new(),
+                time: Duration::ZERO,
+            });
+            return;
+        }
+    };
+    let mut names: Vec<String> = Vec::new();
+    for stmt in program.statements.iter() {
+        if let Stmt::Test(t) = program.ast.stmt(*stmt) {
+            let name = t.name.string.clone().unwrap_or_default();
+            // `select_test` would run both blocks for either name
+            if names.contains(&name) {
+                results.push(TestResult {
+                    file: file.clone(),
+                    name: name.clone(),
+                    failure: Some(format!("[line {}] duplicate test name \"{}\"", t.name.line, name)),
+                    output: String::new(),
+                    time: Duration::ZERO,
+                });
+                return;
+            }
+            names.push(name);
+        }
+    }
+
+    for name in names {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Captured(output.clone())));
+        interpreter.select_test(&name);
+
+        let start = Instant::now();
+        let failure = match interpreter.run(&program) {
+            Ok(()) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        let output = String::from_utf8_lossy(&output.borrow()).into_owned();
+        results.push(TestResult { file: file.clone(), name, failure, output, time: start.elapsed() });
+    }
+}
+
+fn print_human(results: &[TestResult]) {
+    for r in results {
+        let status = if r.failure.is_some() { "FAILED" } else { "ok" };
+        println!("test {} :: {} ... {}", r.file, r.name, status);
+    }
+    let failed: Vec<&TestResult> = results.iter().filter(|r| r.failure.is_some()).collect();
+    for r in failed.iter() {
+        println!("\n---- {} :: {} ----", r.file, r.name);
+        print!("{}", r.output);
+        println!("{}", r.failure.as_ref().unwrap());
+    }
+    println!("\n{} passed; {} failed", results.len() - failed.len(), failed.len());
+}
+
+fn print_tap(results: &[TestResult]) {
+    println!("TAP version 13");
+    println!("1..{}", results.len());
+    for (i, r) in results.iter().enumerate() {
+        match &r.failure {
+            None => println!("ok {} - {} :: {}", i + 1, r.file, r.name),
+            Some(failure) => {
+                println!("not ok {} - {} :: {}", i + 1, r.file, r.name);
+                println!("  ---");
+                println!("  message: |");
+                for line in failure.lines() {
+                    println!("    {}", line);
+                }
+                println!("  ...");
+            }
+        }
+    }
+}
+
+fn print_junit(results: &[TestResult]) {
+    let mut out = io::stdout().lock();
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    _ = writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    _ = writeln!(out, "<testsuites tests=\"{}\" failures=\"{}\">", results.len(), failures);
+
+    let mut i = 0;
+    while i < results.len() {
+        let file = &results[i].file;
+        let suite: Vec<&TestResult> = results[i..].iter().take_while(|r| r.file == *file).collect();
+        let suite_failures = suite.iter().filter(|r| r.failure.is_some()).count();
+        _ = writeln!(
+            out,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+            escape_xml(file),
+            suite.len(),
+            suite_failures
+        );
+        for r in suite.iter() {
+            let open = format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.6}\"",
+                escape_xml(&r.name),
+                escape_xml(file),
+                r.time.as_secs_f64()
+            );
+            match &r.failure {
+                None => _ = writeln!(out, "{}/>", open),
+                Some(failure) => {
+                    let message = failure.lines().next().unwrap_or("");
+                    _ = writeln!(out, "{}>", open);
+                    _ = writeln!(out, "      <failure message=\"{}\">{}</failure>", escape_xml(message), escape_xml(failure));
+                    if !r.output.is_empty() {
+                        _ = writeln!(out, "      <system-out>{}</system-out>", escape_xml(&r.output));
+                    }
+                    _ = writeln!(out, "    </testcase>");
+                }
+            }
+        }
+        _ = writeln!(out, "  </testsuite>");
+        i += suite.len();
+    }
+    _ = writeln!(out, "</testsuites>");
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// collects `program` output so it can be shown next to a failure
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{options, run, run_file, Format, TestResult};
+
+    fn file(name: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("lox-test-{}-{}.lox", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn results(name: &str, source: &str) -> Vec<TestResult> {
+        let path = file(name, source);
+        let mut results = Vec::new();
+        run_file(&path, &mut results);
+        fs::remove_file(path).unwrap();
+        results
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn each_test_runs_once_with_the_top_level() {
+        let results = results(
+            "top-level",
+            "var n = 1;\ntest \"one\" { assert_eq(n, 1); }\ntest \"two\" { assert_eq(n + 1, 2); }\n",
+        );
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["one", "two"]);
+        assert!(results.iter().all(|r| r.failure.is_none()));
+    }
+
+    #[test]
+    fn failures_keep_the_error_and_output() {
+        let results = results("failure", "test \"bad\" { print \"before\"; assert_eq(1, 2); }\n");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].failure.as_ref().unwrap().contains("[line 1]"));
+        assert_eq!(results[0].output, "before\n");
+    }
+
+    #[test]
+    fn duplicate_names_are_an_error() {
+        let results = results("duplicate", "test \"same\" { }\ntest \"same\" { }\n");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].failure.as_ref().unwrap().contains("[line 2] duplicate test name \"same\""));
+    }
+
+    #[test]
+    fn syntax_errors_fail_the_file() {
+        let results = results("syntax", "test \"broken\" { print; }\n");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "<parse>");
+    }
+
+    #[test]
+    fn options_are_checked() {
+        assert!(matches!(options(&args(&["--format", "tap", "a.lox"])), Ok((Format::Tap, paths)) if paths == vec![PathBuf::from("a.lox")]));
+        assert!(matches!(options(&[]), Ok((Format::Human, paths)) if paths == vec![PathBuf::from(".")]));
+        assert!(options(&args(&["--format", "xml"])).is_err());
+        assert!(options(&args(&["--filter", "x"])).is_err());
+    }
+
+    #[test]
+    fn missing_paths_and_paths_without_tests_fail() {
+        let missing = std::env::temp_dir().join("lox-test-does-not-exist.lox");
+        assert_eq!(run(&[missing.display().to_string()]), 64);
+
+        let empty = file("empty", "print 1;\n");
+        assert_eq!(run(&[empty.display().to_string()]), 1);
+        fs::remove_file(empty).unwrap();
+    }
+}
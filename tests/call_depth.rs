@@ -0,0 +1,48 @@
+// `--max-call-depth` must stop a script at the same call, with the same trace, on both backends
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(backend: &str, depth: &str, script: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .args(["run", &format!("--backend={}", backend), "--max-call-depth", depth, script])
+        .output()
+        .unwrap()
+}
+
+// (stdout, stderr, exit code) on the tree-walker, after checking the VM gives the same
+fn both(name: &str, source: &str, depth: &str) -> (String, String, Option<i32>) {
+    let path = std::env::temp_dir().join(format!("lox-depth-{}-{}.lox", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let tree = run("tree", depth, path.to_str().unwrap());
+    let vm = run("vm", depth, path.to_str().unwrap());
+    fs::remove_file(path).unwrap();
+
+    let tree = (String::from_utf8_lossy(&tree.stdout).into_owned(), String::from_utf8_lossy(&tree.stderr).into_owned(), tree.status.code());
+    let vm = (String::from_utf8_lossy(&vm.stdout).into_owned(), String::from_utf8_lossy(&vm.stderr).into_owned(), vm.status.code());
+    assert_eq!(tree, vm);
+    tree
+}
+
+#[test]
+fn a_depth_of_one_leaves_no_room_for_a_call() {
+    let (stdout, stderr, code) = both("one", "print 1;\nassert(true);\nprint 2;\n", "1");
+    assert_eq!(stdout, "1\n");
+    assert_eq!(stderr, "Stack overflow.\n[line 2] in script\n[line 2]\n");
+    assert_eq!(code, Some(70));
+}
+
+#[test]
+fn the_trace_shows_the_line_of_the_call() {
+    let (_, stderr, code) = both("lines", "print\n  1 +\n  gc();\n", "1");
+    assert_eq!(stderr, "Stack overflow.\n[line 3] in script\n[line 3]\n");
+    assert_eq!(code, Some(70));
+}
+
+#[test]
+fn a_native_fits_in_two_frames() {
+    let (stdout, stderr, code) = both("two", "print assert_eq(assert(true), nil);\nprint gc();\n", "2");
+    assert_eq!(stdout, "nil\n0\n");
+    assert!(stderr.is_empty());
+    assert_eq!(code, Some(0));
+}
@@ -0,0 +1,79 @@
+// compiles scripts with `lox compile` and runs the .loxc files it writes
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn lox(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter")).args(args).output().unwrap()
+}
+
+// a fresh directory holding `name.lox`, returns the script's path
+fn script(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lox-compile-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.lox", name));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn compiled_files_run_like_their_source() {
+    let source = script("same", "var a = \"x\";\nprint a + \"y\";\nprint 2 * 3;\n");
+    let compiled = source.with_extension("loxc");
+    assert_eq!(lox(&[Path::new("compile"), &source]).status.code(), Some(0));
+    let from_source = lox(&[&source]);
+    let from_compiled = lox(&[&compiled]);
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    assert_eq!(from_compiled.status.code(), Some(0));
+    assert_eq!(from_compiled.stdout, from_source.stdout);
+    assert!(from_compiled.stderr.is_empty());
+}
+
+#[test]
+fn corrupt_and_truncated_files_are_refused() {
+    let source = script("corrupt", "print 1;\n");
+    let compiled = source.with_extension("loxc");
+    assert_eq!(lox(&[Path::new("compile"), &source]).status.code(), Some(0));
+    let bytes = fs::read(&compiled).unwrap();
+
+    let mut flipped = bytes.clone();
+    let last = flipped.len() - 1;
+    flipped[last] ^= 0xff;
+    fs::write(&compiled, flipped).unwrap();
+    let corrupt = lox(&[&compiled]);
+    fs::write(&compiled, &bytes[..bytes.len() / 2]).unwrap();
+    let truncated = lox(&[&compiled]);
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    assert_eq!(corrupt.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&corrupt.stderr).contains("checksum mismatch, the file is corrupt"));
+    assert_eq!(truncated.status.code(), Some(65));
+    assert!(corrupt.stdout.is_empty() && truncated.stdout.is_empty());
+}
+
+#[test]
+fn a_stale_file_warns_but_runs() {
+    let source = script("stale", "print 1;\n");
+    let compiled = source.with_extension("loxc");
+    assert_eq!(lox(&[Path::new("compile"), &source]).status.code(), Some(0));
+    fs::write(&source, "print 2;\n").unwrap();
+    let output = lox(&[&compiled]);
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is out of date with its source"));
+}
+
+#[test]
+fn syntax_errors_write_nothing() {
+    let source = script("syntax", "print ;\n");
+    let output = lox(&[Path::new("compile"), &source]);
+    let written = source.with_extension("loxc").exists();
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+
+    assert_eq!(output.status.code(), Some(65));
+    assert!(!written);
+}
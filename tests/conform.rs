@@ -0,0 +1,24 @@
+// runs the annotated scripts in tests/conform on both backends
+
+use std::process::Command;
+
+fn conform(backend: &str) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .arg("conform")
+        .arg(format!("--backend={}", backend))
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/conform"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout);
+}
+
+#[test]
+fn the_tree_walker_conforms() {
+    conform("tree");
+}
+
+#[test]
+fn the_vm_conforms() {
+    conform("vm");
+}
@@ -0,0 +1,246 @@
+// drives a whole `lox dap` session the way an editor would
+
+use std::fs;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use rust_lox_interpreter::tools::json::Json;
+use rust_lox_interpreter::tools::rpc::{read_frame, write_message, Frame};
+
+struct Client {
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    seq: i64,
+    // events that arrived while waiting for something else
+    events: Vec<Json>,
+}
+
+impl Client {
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        self.seq += 1;
+        let request = Json::object(vec![
+            ("seq", Json::Number(self.seq as f64)),
+            ("type", Json::str("request")),
+            ("command", Json::str(command)),
+            ("arguments", arguments),
+        ]);
+        write_message(&mut self.input, &request).unwrap();
+        loop {
+            let message = self.read();
+            if message.get("type").as_str() == Some("response") {
+                assert_eq!(message.get("request_seq").as_i64(), Some(self.seq));
+                assert_eq!(message.get("command").as_str(), Some(command));
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    fn read(&mut self) -> Json {
+        match read_frame(&mut self.output).expect("adapter closed the stream") {
+            Frame::Message(m) => m,
+            Frame::Malformed => panic!("adapter sent a malformed message"),
+        }
+    }
+
+    fn event(&mut self, name: &str) -> Json {
+        loop {
+            if let Some(i) = self.events.iter().position(|e| e.get("event").as_str() == Some(name)) {
+                return self.events.remove(i);
+            }
+            let message = self.read();
+            self.events.push(message);
+        }
+    }
+}
+
+// writes `source` to a file of its own for the test called `name`
+fn program(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("lox-dap-{}-{}.lox", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+// starts an adapter and gets it through initialize
+fn start() -> (Child, Client) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .arg("dap")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut client = Client {
+        input: child.stdin.take().unwrap(),
+        output: BufReader::new(child.stdout.take().unwrap()),
+        seq: 0,
+        events: Vec::new(),
+    };
+
+    let init = client.request("initialize", Json::object(vec![("adapterID", Json::str("lox"))]));
+    assert_eq!(init.get("body").get("supportsConfigurationDoneRequest").as_bool(), Some(true));
+    client.event("initialized");
+    (child, client)
+}
+
+#[test]
+fn scripted_session() {
+    let program = program("session", "var a = 1;\nvar b = a + 1;\nprint a + b;\nprint b;\n");
+    let (mut child, mut client) = start();
+
+    let path = program.to_str().unwrap();
+    client.request("launch", Json::object(vec![("program", Json::str(path))]));
+    let breakpoints = client.request(
+        "setBreakpoints",
+        Json::object(vec![
+            ("source", Json::object(vec![("path", Json::str(path))])),
+            ("breakpoints", Json::Array(vec![Json::object(vec![("line", Json::Number(3.0))])])),
+        ]),
+    );
+    assert_eq!(breakpoints.get("body").get("breakpoints").as_array()[0].get("verified").as_bool(), Some(true));
+    client.request("configurationDone", Json::object(vec![]));
+
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("body").get("reason").as_str(), Some("breakpoint"));
+
+    let threads = client.request("threads", Json::Null);
+    let thread_id = threads.get("body").get("threads").as_array()[0].get("id").clone();
+
+    let trace = client.request("stackTrace", Json::object(vec![("threadId", thread_id.clone())]));
+    let frame = &trace.get("body").get("stackFrames").as_array()[0];
+    assert_eq!(frame.get("line").as_i64(), Some(3));
+    assert_eq!(frame.get("source").get("path").as_str(), Some(path));
+
+    let scopes = client.request("scopes", Json::object(vec![("frameId", frame.get("id").clone())]));
+    let globals = &scopes.get("body").get("scopes").as_array()[0];
+    assert_eq!(globals.get("name").as_str(), Some("globals"));
+
+    let variables = client.request(
+        "variables",
+        Json::object(vec![("variablesReference", globals.get("variablesReference").clone())]),
+    );
+    let variables: Vec<(String, String)> = variables
+        .get("body")
+        .get("variables")
+        .as_array()
+        .iter()
+        .map(|v| (v.get("name").as_str().unwrap().to_owned(), v.get("value").as_str().unwrap().to_owned()))
+        .collect();
+    assert_eq!(variables, vec![("a".to_owned(), "1".to_owned()), ("b".to_owned(), "2".to_owned())]);
+
+    let evaluated = client.request("evaluate", Json::object(vec![("expression", Json::str("a * 10 + b"))]));
+    assert_eq!(evaluated.get("body").get("result").as_str(), Some("12"));
+    let failed = client.request("evaluate", Json::object(vec![("expression", Json::str("nope"))]));
+    assert_eq!(failed.get("success").as_bool(), Some(false));
+
+    client.request("next", Json::object(vec![("threadId", thread_id.clone())]));
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("body").get("reason").as_str(), Some("step"));
+    let trace = client.request("stackTrace", Json::object(vec![("threadId", thread_id.clone())]));
+    assert_eq!(trace.get("body").get("stackFrames").as_array()[0].get("line").as_i64(), Some(4));
+
+    client.request("continue", Json::object(vec![("threadId", thread_id)]));
+    let exited = client.event("exited");
+    assert_eq!(exited.get("body").get("exitCode").as_i64(), Some(0));
+    client.event("terminated");
+
+    let output: Vec<Json> = client.events.iter().filter(|e| e.get("event").as_str() == Some("output")).cloned().collect();
+    assert_eq!(output.len(), 2);
+
+    client.request("disconnect", Json::object(vec![]));
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+}
+
+#[test]
+fn runtime_error() {
+    let program = program("error", "print 1;\nprint 1 + \"a\";\nprint 2;\n");
+    let (mut child, mut client) = start();
+
+    client.request("launch", Json::object(vec![("program", Json::str(program.to_str().unwrap()))]));
+    client.request("configurationDone", Json::object(vec![]));
+
+    // the adapter stops at the error so the client can look around first
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("body").get("reason").as_str(), Some("exception"));
+    assert_eq!(stopped.get("body").get("text").as_str(), Some("invalid arguments to binary operation"));
+    let trace = client.request("stackTrace", Json::object(vec![("threadId", Json::Number(1.0))]));
+    assert_eq!(trace.get("body").get("stackFrames").as_array()[0].get("line").as_i64(), Some(2));
+
+    client.request("continue", Json::object(vec![("threadId", Json::Number(1.0))]));
+    let exited = client.event("exited");
+    assert_eq!(exited.get("body").get("exitCode").as_i64(), Some(70));
+    client.event("terminated");
+
+    let output: Vec<(String, String)> = client
+        .events
+        .iter()
+        .filter(|e| e.get("event").as_str() == Some("output"))
+        .map(|e| {
+            let body = e.get("body");
+            (body.get("category").as_str().unwrap().to_owned(), body.get("output").as_str().unwrap().to_owned())
+        })
+        .collect();
+    assert_eq!(output.len(), 2);
+    assert_eq!(output[0], ("stdout".to_owned(), "1\n".to_owned()));
+    assert_eq!(output[1].0, "stderr");
+    assert!(output[1].1.starts_with("invalid arguments to binary operation\n[line 2]"));
+
+    client.request("disconnect", Json::object(vec![]));
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+}
+
+fn set_breakpoints(client: &mut Client, path: &str, lines: &[f64]) -> Vec<Json> {
+    let breakpoints = lines.iter().map(|l| Json::object(vec![("line", Json::Number(*l))])).collect();
+    let response = client.request(
+        "setBreakpoints",
+        Json::object(vec![
+            ("source", Json::object(vec![("path", Json::str(path))])),
+            ("breakpoints", Json::Array(breakpoints)),
+        ]),
+    );
+    response.get("body").get("breakpoints").as_array().to_vec()
+}
+
+#[test]
+fn stop_on_entry_keeps_breakpoints() {
+    let program = program("entry", "print 1;\nprint 2;\nprint 3;\n");
+    let (mut child, mut client) = start();
+    let path = program.to_str().unwrap();
+
+    // breakpoints may arrive before launch
+    set_breakpoints(&mut client, path, &[3.0]);
+    client.request("launch", Json::object(vec![("program", Json::str(path)), ("stopOnEntry", Json::Bool(true))]));
+    client.request("configurationDone", Json::object(vec![]));
+
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("body").get("reason").as_str(), Some("entry"));
+    client.request("continue", Json::object(vec![("threadId", Json::Number(1.0))]));
+    let stopped = client.event("stopped");
+    assert_eq!(stopped.get("body").get("reason").as_str(), Some("breakpoint"));
+    let trace = client.request("stackTrace", Json::object(vec![("threadId", Json::Number(1.0))]));
+    assert_eq!(trace.get("body").get("stackFrames").as_array()[0].get("line").as_i64(), Some(3));
+
+    client.request("continue", Json::object(vec![("threadId", Json::Number(1.0))]));
+    assert_eq!(client.event("exited").get("body").get("exitCode").as_i64(), Some(0));
+    client.request("disconnect", Json::object(vec![]));
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+}
+
+#[test]
+fn breakpoints_before_line_1_are_not_verified() {
+    let program = program("lines", "print 1;\n");
+    let (mut child, mut client) = start();
+    let path = program.to_str().unwrap();
+
+    let breakpoints = set_breakpoints(&mut client, path, &[0.0, -1.0, 1.0]);
+    let verified: Vec<Option<bool>> = breakpoints.iter().map(|b| b.get("verified").as_bool()).collect();
+    assert_eq!(verified, vec![Some(false), Some(false), Some(true)]);
+
+    client.request("disconnect", Json::object(vec![]));
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(program).unwrap();
+}
@@ -0,0 +1,37 @@
+// runs `lox debug` with its commands on stdin
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn debug(name: &str, source: &str, commands: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("lox-debug-{}-{}.lox", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .arg("debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn a_finished_program_exits_with_0() {
+    let output = debug("finished", "print 1;\n", "continue\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("program finished"));
+}
+
+#[test]
+fn a_runtime_error_exits_with_70() {
+    let output = debug("error", "print 1;\nprint 1 + nil;\nprint 2;\n", "continue\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid arguments to binary operation\n[line 2]"));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("\n2\n"));
+}
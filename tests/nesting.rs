@@ -0,0 +1,36 @@
+// pathological nesting must end in a diagnostic, never a stack overflow, on both backends
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(name: &str, source: &str, backend: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("lox-nesting-{}-{}-{}.lox", name, backend, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .args(["run", &format!("--backend={}", backend)])
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn a_10000_term_chain_is_rejected() {
+    let source = format!("print {};\n", vec!["1"; 10000].join(" + "));
+    for backend in ["tree", "vm"] {
+        let output = run("chain", &source, backend);
+        assert_eq!(output.status.code(), Some(65), "{}", backend);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("[line 1] Error at '+': Expression too deeply nested."));
+    }
+}
+
+#[test]
+fn chains_and_groupings_within_the_limit_run() {
+    let source = format!("print {};\nprint {}1{};\n", vec!["1"; 256].join(" + "), "(".repeat(250), ")".repeat(250));
+    for backend in ["tree", "vm"] {
+        let output = run("limit", &source, backend);
+        assert_eq!(output.status.code(), Some(0), "{}", backend);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "256\n1\n");
+    }
+}
@@ -0,0 +1,28 @@
+// runs every script in tests/conform on both backends, which must agree on output, errors and exit code
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(backend: &str, script: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .arg("run")
+        .arg(format!("--backend={}", backend))
+        .arg(script)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn the_backends_agree() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/conform");
+    let mut scripts: Vec<String> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path().to_string_lossy().into_owned()).collect();
+    scripts.sort();
+    assert!(!scripts.is_empty());
+    for script in scripts.iter() {
+        let tree = run("tree", script);
+        let vm = run("vm", script);
+        assert_eq!(String::from_utf8_lossy(&tree.stdout), String::from_utf8_lossy(&vm.stdout), "stdout of {}", script);
+        assert_eq!(String::from_utf8_lossy(&tree.stderr), String::from_utf8_lossy(&vm.stderr), "stderr of {}", script);
+        assert_eq!(tree.status.code(), vm.status.code(), "exit code of {}", script);
+    }
+}
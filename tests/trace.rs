@@ -0,0 +1,30 @@
+// runs `lox run --trace-file` and checks what ends up in the file
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn trace_file_gets_the_trace_and_stdout_the_output() {
+    let dir = std::env::temp_dir();
+    let script = dir.join(format!("lox-trace-{}.lox", std::process::id()));
+    let trace = dir.join(format!("lox-trace-{}.txt", std::process::id()));
+    fs::write(&script, "var a = 2;\nprint a;\nassert(a == 2);\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-lox-interpreter"))
+        .arg("run")
+        .arg("--trace-file")
+        .arg(&trace)
+        .arg(&script)
+        .output()
+        .unwrap();
+    let written = fs::read_to_string(&trace).unwrap();
+    fs::remove_file(script).unwrap();
+    fs::remove_file(trace).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert!(output.stderr.is_empty());
+    assert_eq!(
+        written,
+        "[line 1] var a = 2;\na = 2\n[line 2] print a;\n[line 3] assert(a == 2);\n  call assert(true)\n  return assert -> nil\n=> nil\n"
+    );
+}